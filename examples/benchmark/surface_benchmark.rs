@@ -0,0 +1,62 @@
+// Times surface generation at high resolution. Run it with and without the parallel feature
+//   cargo run --release --example surface_benchmark
+//   cargo run --release --example surface_benchmark --features parallel
+// and compare the times; the checksums must match, as both paths give identical output.
+use std::time::{Duration, Instant};
+use wgpu_textures::parallel;
+use wgpu_textures::surface_data::{ColorSource, IParametricSurface, ISimpleSurface, ISurfaceOutput};
+
+// FNV-1a over the bits of every vertex attribute and index
+fn checksum(out:&ISurfaceOutput) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut add = |x:u32| {
+        hash ^= x as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    };
+    let channels = [&out.positions, &out.normals, &out.bitangents, &out.colors, &out.colors2];
+    for v in channels.iter().flat_map(|c| c.iter()).flatten() {
+        add(v.to_bits());
+    }
+    for v in out.tangents.iter().flatten().chain(out.uvs.iter().flatten()) {
+        add(v.to_bits());
+    }
+    for i in out.indices.iter().chain(out.indices2.iter()) {
+        add(i as u32);
+    }
+    hash
+}
+
+// best of a few runs
+fn time<F:FnMut() -> ISurfaceOutput>(mut f:F) -> (Duration, ISurfaceOutput) {
+    let mut best = Duration::MAX;
+    let mut out = ISurfaceOutput::default();
+    for _ in 0..3 {
+        let start = Instant::now();
+        out = f();
+        best = best.min(start.elapsed());
+    }
+    (best, out)
+}
+
+fn main() {
+    println!("parallel: {}", parallel::enabled());
+    let resolution = 1000;
+    for (name, color_source) in [("klein_bottle", ColorSource::Coordinate), ("torus", ColorSource::Coordinate), 
+        ("seashell", ColorSource::MeanCurvature)] {
+        let mut surface = IParametricSurface {
+            u_resolution: resolution,
+            v_resolution: resolution,
+            color_source: color_source.clone(),
+            ..Default::default()
+        };
+        surface.set_surface(name).unwrap();
+        let (elapsed, out) = time(|| surface.new().unwrap());
+        println!("{:>14} {:?} {}x{}: {} vertices in {:.1} ms, checksum {:016x}", name, color_source, 
+            resolution, resolution, out.positions.len(), elapsed.as_secs_f64() * 1e3, checksum(&out));
+    }
+
+    let mut surface = ISimpleSurface { x_resolution: resolution, z_resolution: resolution, ..Default::default() };
+    let (elapsed, out) = time(|| surface.new().unwrap());
+    println!("{:>14} {}x{}: {} vertices in {:.1} ms, checksum {:016x}", "sinc", resolution, resolution, 
+        out.positions.len(), elapsed.as_secs_f64() * 1e3, checksum(&out));
+}
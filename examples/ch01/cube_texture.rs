@@ -19,7 +19,9 @@ struct Vertex {
 }
 
 fn create_vertices(ul: f32, vl: f32) -> (Vec<Vertex>, Vec<u16>) {
-    let (pos, _, norm, uv, ind, _) = vd::create_cube_data(2.5);
+    let mesh = vd::create_cube_data(2.5);
+    let (pos, ind) = (mesh.positions, mesh.indices.to_u16().unwrap());
+    let (norm, uv) = (mesh.normals.unwrap(), mesh.uvs.unwrap());
     let mut data: Vec<Vertex> = vec![];
     for i in 0..pos.len() {
         data.push(Vertex {
@@ -19,7 +19,9 @@ struct Vertex {
 }
 
 fn create_vertices() -> (Vec<Vertex>, Vec<u16>) {
-    let (pos, norm, uv, ind, _) = vd::create_sphere_data(2.0, 20, 32);
+    let mesh = vd::create_sphere_data(2.0, 20, 32);
+    let (pos, ind) = (mesh.positions, mesh.indices.to_u16().unwrap());
+    let (norm, uv) = (mesh.normals.unwrap(), mesh.uvs.unwrap());
     let mut data: Vec<Vertex> = vec![];
     for i in 0..pos.len() {
         data.push(Vertex {
@@ -19,7 +19,9 @@ struct Vertex {
 }
 
 fn create_vertices() -> (Vec<Vertex>, Vec<u16>) {
-    let (pos, _, norm, _, ind, _) = vd::create_cube_data(2.5);
+    let mesh = vd::create_cube_data(2.5);
+    let (pos, ind) = (mesh.positions, mesh.indices.to_u16().unwrap());
+    let norm = mesh.normals.unwrap();
     let uv = vd::create_cube_uv();
     let mut data: Vec<Vertex> = vec![];
     for i in 0..pos.len() {
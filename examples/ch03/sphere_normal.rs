@@ -21,7 +21,9 @@ struct Vertex {
 }
 
 fn create_vertices(ul: f32, vl: f32) -> (Vec<Vertex>, Vec<u16>) {
-    let (pos, normal, uv, ind, _) = vd::create_sphere_data(2.5, 20, 32);
+    let mesh = vd::create_sphere_data(2.5, 20, 32);
+    let (pos, ind) = (mesh.positions, mesh.indices.to_u16().unwrap());
+    let (normal, uv) = (mesh.normals.unwrap(), mesh.uvs.unwrap());
     let tan = vd::create_tangent_data(&pos, &normal, &uv, &ind);
 
     let mut data: Vec<Vertex> = vec![];
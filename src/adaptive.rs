@@ -0,0 +1,292 @@
+#![allow(dead_code)]
+use cgmath::*;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use super::surface_registry::{Domain, Seam};
+
+// Deepest quadtree level; keys of grid points are integers at this level.
+const MAX_LEVEL: u32 = 14;
+
+// Settings of the adaptive tessellation of IParametricSurface. A cell of the parameter domain
+// is split while the surface leaves its two triangles by more than max_error (in the units of
+// the output positions, i.e. relative to scale) or its normals turn by more than max_angle
+// degrees, until max_vertices is reached.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveTessellation {
+    pub max_error: f32,
+    pub max_angle: f32,
+    pub max_vertices: usize,
+    pub base_resolution: u16,  // cells along u and v before refinement
+}
+
+impl Default for AdaptiveTessellation {
+    fn default() -> Self {
+        Self {
+            max_error: 0.002,
+            max_angle: 15.0,
+            max_vertices: 20000,
+            base_resolution: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Cell {
+    level: u32,
+    i: u32,  // along u
+    j: u32,  // along v
+}
+
+// A Seam in terms of base cells: the point t on the min edge meets t + k (shifted) or k - t
+// (reflected) on the max edge, modulo the base resolution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Wrap {
+    None,
+    Shifted(u32),
+    Reflected(u32),
+}
+
+impl Wrap {
+    // Seams whose offset falls between base cells are left open.
+    fn from_seam(seam:Seam, n:u32) -> Wrap {
+        let cells = |offset:f32| {
+            let k = offset * n as f32;
+            if (k - k.round()).abs() < 1e-3 { Some(k.round() as u32 % n) } else { None }
+        };
+        match seam {
+            Seam::Periodic(offset) => cells(offset).map_or(Wrap::None, Wrap::Shifted),
+            Seam::Twisted(offset) => cells(offset).map_or(Wrap::None, Wrap::Reflected),
+            Seam::Open => Wrap::None,
+        }
+    }
+}
+
+// Triangulation of the parameter domain: (u, v) per vertex with triangle and line indices.
+pub(crate) struct ParamMesh {
+    pub params: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    pub indices2: Vec<u32>,
+}
+
+struct Quadtree<'a> {
+    options: AdaptiveTessellation,
+    domain: Domain,
+    point: &'a dyn Fn(f32, f32) -> Vector3<f32>,
+    normal: &'a dyn Fn(f32, f32) -> Vector3<f32>,
+    wrap: [Wrap; 2],  // across the u edges and across the v edges
+    leaves: HashSet<Cell>,
+    heap: BinaryHeap<(u32, Cell)>,
+    corners: HashSet<(u32, u32)>,  // keys of the leaf corners, for the vertex budget
+}
+
+impl<'a> Quadtree<'a> {
+    fn cells(&self, level:u32) -> u32 {
+        (self.options.base_resolution.max(1) as u32) << level
+    }
+
+    fn param(&self, key:(u32, u32)) -> (f32, f32) {
+        let (umin, umax, vmin, vmax) = self.domain;
+        let n = self.cells(MAX_LEVEL) as f32;
+        (umin + (umax - umin) * key.0 as f32 / n, vmin + (vmax - vmin) * key.1 as f32 / n)
+    }
+
+    // key of the point (i, j) on the grid of the given level
+    fn key(level:u32, i:u32, j:u32) -> (u32, u32) {
+        (i << (MAX_LEVEL - level), j << (MAX_LEVEL - level))
+    }
+
+    fn corner_keys(c:Cell) -> [(u32, u32); 4] {
+        [Self::key(c.level, c.i, c.j), Self::key(c.level, c.i, c.j + 1),
+            Self::key(c.level, c.i + 1, c.j + 1), Self::key(c.level, c.i + 1, c.j)]
+    }
+
+    // Refinement priority of a cell, > 1 when it needs splitting: the distance of the surface
+    // from the bilinear patch through the corners at the edge midpoints and the center, and
+    // the angle between the normals at the corners and at the center.
+    fn score(&self, c:Cell) -> f32 {
+        let [k00, _, k11, _] = Self::corner_keys(c);
+        let ((u0, v0), (u1, v1)) = (self.param(k00), self.param(k11));
+        let (um, vm) = (0.5 * (u0 + u1), 0.5 * (v0 + v1));
+        let p = |u, v| (self.point)(u, v);
+        let (p00, p01, p11, p10) = (p(u0, v0), p(u0, v1), p(u1, v1), p(u1, v0));
+        let samples = [
+            (um, v0, (p00 + p10) * 0.5), (um, v1, (p01 + p11) * 0.5),
+            (u0, vm, (p00 + p01) * 0.5), (u1, vm, (p10 + p11) * 0.5),
+            (um, vm, (p00 + p01 + p11 + p10) * 0.25),
+        ];
+        let error = samples.iter().map(|&(u, v, q)| (p(u, v) - q).magnitude()).fold(0.0, f32::max);
+
+        let n = |u, v| (self.normal)(u, v);
+        let center = n(um, vm);
+        let angle = [n(u0, v0), n(u0, v1), n(u1, v1), n(u1, v0)].iter()
+            .map(|m| center.angle(*m).0.to_degrees()).fold(0.0, f32::max);
+
+        let score = (error / self.options.max_error).max(angle / self.options.max_angle);
+        // singular cells (NaN normals at poles, say) are left to the vertex budget
+        if score.is_finite() { score } else { 0.0 }
+    }
+
+    fn push(&mut self, c:Cell) {
+        if c.level + 1 < MAX_LEVEL {
+            let score = self.score(c);
+            if score > 1.0 {
+                self.heap.push((score.to_bits(), c));
+            }
+        }
+    }
+
+    // The cell next to c in direction dir (0: -u, 1: +u, 2: -v, 3: +v) on the same level,
+    // across glued edges; None at an open edge of the domain.
+    fn neighbor(&self, c:Cell, dir:usize) -> Option<Cell> {
+        let n = self.cells(c.level) as i64;
+        let (mut i, mut j) = (c.i as i64, c.j as i64);
+        match dir {
+            0 => i -= 1,
+            1 => i += 1,
+            2 => j -= 1,
+            _ => j += 1,
+        }
+        // index of the cell across a glued edge along the other parameter; leaving through
+        // the min edge applies the gluing map, leaving through the max edge its inverse
+        let across = |wrap:Wrap, t:i64, from_min:bool| -> Option<i64> {
+            match wrap {
+                Wrap::None => None,
+                Wrap::Shifted(k) => {
+                    let o = (k as i64) << c.level;
+                    Some((if from_min { t + o } else { t - o }).rem_euclid(n))
+                }
+                Wrap::Reflected(k) => Some((((k as i64) << c.level) - 1 - t).rem_euclid(n)),
+            }
+        };
+        if i < 0 || i >= n {
+            j = across(self.wrap[0], j, i < 0)?;
+            i = i.rem_euclid(n);
+        } else if j < 0 || j >= n {
+            i = across(self.wrap[1], i, j < 0)?;
+            j = j.rem_euclid(n);
+        }
+        Some(Cell { level: c.level, i: i as u32, j: j as u32 })
+    }
+
+    // The leaf containing the cell, or None when the cell is split further.
+    fn leaf_covering(&self, mut c:Cell) -> Option<Cell> {
+        loop {
+            if self.leaves.contains(&c) {
+                return Some(c);
+            }
+            if c.level == 0 {
+                return None;
+            }
+            c = Cell { level: c.level - 1, i: c.i / 2, j: c.j / 2 };
+        }
+    }
+
+    // Splits a leaf into four. Coarser neighbors are split first, so that adjacent leaves
+    // differ by at most one level and every edge has at most one hanging vertex.
+    fn split(&mut self, c:Cell) {
+        if !self.leaves.contains(&c) {
+            return;
+        }
+        for dir in 0..4 {
+            let leaf = self.neighbor(c, dir).and_then(|n| self.leaf_covering(n));
+            if let Some(leaf) = leaf {
+                if leaf.level < c.level {
+                    self.split(leaf);
+                }
+            }
+        }
+        self.leaves.remove(&c);
+        for (di, dj) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let child = Cell { level: c.level + 1, i: 2 * c.i + di, j: 2 * c.j + dj };
+            self.leaves.insert(child);
+            self.corners.extend(Self::corner_keys(child));
+            self.push(child);
+        }
+    }
+}
+
+// Adaptive triangulation of the domain with a restricted quadtree. point and normal give the
+// output position and unit normal at (u, v). Cells are refined worst first, so a tight vertex
+// budget goes to the regions with the largest error. Leaves with a finer neighbor are
+// fanned around their center through the hanging vertices, which keeps the mesh free of
+// cracks, also across the seams that glue edges of the domain together. The triangles have
+// the same orientation as the uniform grid of parametric_surface_data.
+pub(crate) fn tessellate(options:AdaptiveTessellation, domain:Domain, seams:[Seam; 2],
+    point:&dyn Fn(f32, f32) -> Vector3<f32>, normal:&dyn Fn(f32, f32) -> Vector3<f32>) -> ParamMesh {
+    let mut tree = Quadtree {
+        options,
+        domain,
+        point,
+        normal,
+        wrap: seams.map(|seam| Wrap::from_seam(seam, options.base_resolution.max(1) as u32)),
+        leaves: HashSet::new(),
+        heap: BinaryHeap::new(),
+        corners: HashSet::new(),
+    };
+
+    let n = tree.cells(0);
+    for i in 0..n {
+        for j in 0..n {
+            let c = Cell { level: 0, i, j };
+            tree.leaves.insert(c);
+            tree.corners.extend(Quadtree::corner_keys(c));
+            tree.push(c);
+        }
+    }
+
+    // Refine worst first. Every leaf may get a center vertex, so corners plus leaves bounds
+    // the vertex count; the balancing splits of the last step can go slightly over it.
+    while let Some((_, c)) = tree.heap.pop() {
+        if tree.corners.len() + tree.leaves.len() + 8 > options.max_vertices {
+            break;
+        }
+        tree.split(c);
+    }
+
+    // triangulate the leaves in a fixed order
+    let mut leaves: Vec<Cell> = tree.leaves.iter().cloned().collect();
+    leaves.sort();
+    let mut vertices: HashMap<(u32, u32), u32> = HashMap::new();
+    let mut mesh = ParamMesh { params: vec![], indices: vec![], indices2: vec![] };
+    let mut lines: HashSet<(u32, u32)> = HashSet::new();
+    let mut index = |key:(u32, u32), mesh:&mut ParamMesh| *vertices.entry(key).or_insert_with(|| {
+        let (u, v) = tree.param(key);
+        mesh.params.push([u, v]);
+        mesh.params.len() as u32 - 1
+    });
+    for c in leaves {
+        let corners = Quadtree::corner_keys(c);
+        let half = 1u32 << (MAX_LEVEL - c.level - 1);
+        // the corners in the order of the uniform grid, each with the direction of the 
+        // neighbor across the edge that leaves it and the midpoint of that edge
+        let [k00, k01, k11, k10] = corners;
+        let edges = [
+            (k00, 0, (k00.0, k00.1 + half)),
+            (k01, 3, (k01.0 + half, k01.1)),
+            (k11, 1, (k11.0, k11.1 - half)),
+            (k10, 2, (k10.0 - half, k10.1)),
+        ];
+        let mut polygon: Vec<(u32, u32)> = vec![];
+        for (corner, dir, midpoint) in edges {
+            polygon.push(corner);
+            if tree.neighbor(c, dir).is_some_and(|nb| tree.leaf_covering(nb).is_none()) {
+                polygon.push(midpoint);
+            }
+        }
+        let ids: Vec<u32> = polygon.iter().map(|&key| index(key, &mut mesh)).collect();
+        if ids.len() == 4 {
+            mesh.indices.extend([ids[0], ids[1], ids[2], ids[2], ids[3], ids[0]]);
+        } else {
+            let center = index((corners[0].0 + half, corners[0].1 + half), &mut mesh);
+            for k in 0..ids.len() {
+                mesh.indices.extend([center, ids[k], ids[(k + 1) % ids.len()]]);
+            }
+        }
+        for k in 0..ids.len() {
+            let (a, b) = (ids[k], ids[(k + 1) % ids.len()]);
+            if lines.insert((a.min(b), a.max(b))) {
+                mesh.indices2.extend([a, b]);
+            }
+        }
+    }
+    mesh
+}
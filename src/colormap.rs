@@ -24,7 +24,7 @@ pub fn color_lerp(colors:[[f32;3];11], min:f32, max:f32, mut t:f32) -> [f32; 3]{
 }
 
 pub fn colormap_data(colormap_name: &str) -> [[f32; 3]; 11] {
-    let colors = match colormap_name {
+    match colormap_name {
         "hsv" => [[1.0,0.0,0.0],[1.0,0.5,0.0],[0.97,1.0,0.01],[0.0,0.99,0.04],[0.0,0.98,0.52],
             [0.0,0.98,1.0],[0.01,0.49,1.0],[0.03,0.0,0.99],[1.0,0.0,0.96],[1.0,0.0,0.49],[1.0,0.0,0.02]],
 
@@ -102,16 +102,91 @@ pub fn colormap_data(colormap_name: &str) -> [[f32; 3]; 11] {
         // "jet" as default
         _ => [[0.0,0.0,0.51],[0.0,0.24,0.67],[0.01,0.49,0.78],[0.01,0.75,0.89],[0.02,1.0,1.0],
             [0.51,1.0,0.5],[1.0,1.0,0.0],[0.99,0.67,0.0],[0.99,0.33,0.0],[0.98,0.0,0.0],[0.5,0.0,0.0]],
-    };
-
-    colors
+    }
 }
 
 fn convert_f32(a: [[i32; 3]; 11]) -> [[f32; 3]; 11] {
     let b: Vec<[f32;3]> = a.iter().map(|&c| [c[0] as f32, c[1] as f32, c[2] as f32]).collect();
     let mut arr:[[f32; 3]; 11] = [[0.0; 3]; 11];
-    for i in 0..11 {
-        arr[i] = b[i].clone();
-    }
+    arr.copy_from_slice(&b[..11]);
     arr
 }
+
+// Range of the finite values, widened when they are all equal so that color_lerp stays defined.
+pub fn scalar_range(values:&[f32]) -> (f32, f32) {
+    robust_range(values, 0.0)
+}
+
+// scalar_range without the outliers: the given percentage of the finite values is left out
+// at either end, e.g. 1.0 for the range from the 1st to the 99th percentile.
+pub fn robust_range(values:&[f32], percentile:f32) -> (f32, f32) {
+    match percentile_bounds(values, percentile) {
+        None => (0.0, 1.0),
+        Some((min, max)) if min == max => (min - 0.5, max + 0.5),
+        Some(range) => range,
+    }
+}
+
+// Smallest and largest finite value after leaving out the given percentage at either end;
+// None when no value is finite.
+pub fn percentile_bounds(values:&[f32], percentile:f32) -> Option<(f32, f32)> {
+    let finite = values.iter().cloned().filter(|v| v.is_finite());
+    if percentile <= 0.0 {
+        return finite.fold(None, |r, v| Some(r.map_or((v, v), |(min, max):(f32, f32)| (min.min(v), max.max(v)))));
+    }
+    let mut sorted: Vec<f32> = finite.collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(f32::total_cmp);
+    let q = percentile.min(50.0) / 100.0;
+    let at = |q:f32| sorted[((sorted.len() - 1) as f32 * q).round() as usize];
+    Some((at(q), at(1.0 - q)))
+}
+
+// Colors of a per-vertex scalar field over its finite range; values that are not finite get
+// the lowest color.
+pub fn scalar_colors(colormap_name:&str, values:&[f32]) -> Vec<[f32; 3]> {
+    let colors = colormap_data(colormap_name);
+    let (min, max) = scalar_range(values);
+    values.iter().map(|&v| color_lerp(colors, min, max, if v.is_finite() { v } else { min })).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_skip_non_finite_values() {
+        let values = [1.0, f32::NAN, 3.0, f32::INFINITY, -2.0, f32::NEG_INFINITY];
+        assert_eq!(percentile_bounds(&values, 0.0), Some((-2.0, 3.0)));
+        assert_eq!(percentile_bounds(&values, 10.0), Some((-2.0, 3.0)));
+        assert_eq!(percentile_bounds(&[], 0.0), None);
+        assert_eq!(percentile_bounds(&[f32::NAN, f32::INFINITY], 5.0), None);
+    }
+
+    #[test]
+    fn percentiles() {
+        let values: Vec<f32> = (0..=100).rev().map(|v| v as f32).collect();
+        assert_eq!(percentile_bounds(&values, 10.0), Some((10.0, 90.0)));
+        // more than half from either end leaves the median
+        assert_eq!(percentile_bounds(&values, 80.0), Some((50.0, 50.0)));
+    }
+
+    #[test]
+    fn robust_range_leaves_out_outliers() {
+        let mut values: Vec<f32> = (0..100).map(|v| v as f32).collect();
+        values.push(1.0e6);
+        assert_eq!(robust_range(&values, 0.0), (0.0, 1.0e6));
+        assert_eq!(robust_range(&values, 1.0), (1.0, 99.0));
+        assert_eq!(scalar_range(&values), (0.0, 1.0e6));
+    }
+
+    // the range stays usable by color_lerp
+    #[test]
+    fn degenerate_ranges() {
+        assert_eq!(robust_range(&[2.0, 2.0, f32::NAN], 0.0), (1.5, 2.5));
+        assert_eq!(robust_range(&[], 5.0), (0.0, 1.0));
+        assert_eq!(robust_range(&[f32::NAN], 0.0), (0.0, 1.0));
+    }
+}
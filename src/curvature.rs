@@ -0,0 +1,186 @@
+#![allow(dead_code)]
+use cgmath::*;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use super::topology::{weld_by_position, weld_tolerance};
+
+// Curvatures at a surface point. The sign follows the normal: curvatures are positive where
+// the surface bends away from its normal, so a sphere with outward normals has
+// k1 = k2 = mean = 1/r and gaussian = 1/r^2. k1 >= k2 are the principal curvatures.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Curvature {
+    pub gaussian: f32,
+    pub mean: f32,
+    pub k1: f32,
+    pub k2: f32,
+}
+
+impl Curvature {
+    pub fn from_gaussian_mean(gaussian:f32, mean:f32) -> Self {
+        // H^2 - K is never negative on a smooth surface; clamp the discretization error
+        let d = (mean * mean - gaussian).max(0.0).sqrt();
+        Self { gaussian, mean, k1: mean + d, k2: mean - d }
+    }
+
+    // From the first (e, f, g) and second (l, m, n) fundamental forms.
+    pub fn from_fundamental_forms(e:f32, f:f32, g:f32, l:f32, m:f32, n:f32) -> Self {
+        let det = e * g - f * f;
+        if det.abs() < 1e-20 {
+            return Self::default();
+        }
+        Self::from_gaussian_mean((l * n - m * m) / det, (e * n - 2.0 * f * m + g * l) / (2.0 * det))
+    }
+
+    // From the first and second partial derivatives of a parametric surface P(u, v), with
+    // the normal along pu x pv.
+    pub fn from_partials(pu:Vector3<f32>, pv:Vector3<f32>, puu:Vector3<f32>, puv:Vector3<f32>,
+        pvv:Vector3<f32>) -> Self {
+        let n = pu.cross(pv);
+        if n.magnitude2() == 0.0 {
+            return Self::default();
+        }
+        let n = n.normalize();
+        Self::from_fundamental_forms(pu.dot(pu), pu.dot(pv), pv.dot(pv),
+            -puu.dot(n), -puv.dot(n), -pvv.dot(n))
+    }
+
+    // Flips the sign convention, for surfaces whose normal is -(pu x pv).
+    pub fn flipped(self) -> Self {
+        Self { gaussian: self.gaussian, mean: -self.mean, k1: -self.k2, k2: -self.k1 }
+    }
+}
+
+// Discrete curvature per vertex of a triangle mesh: the angle deficit over the vertex area
+// for the Gaussian curvature and the cotangent Laplacian for the mean curvature (Meyer et
+// al., 2003, with barycentric vertex areas). Vertices at the same position, up to
+// topology::weld_tolerance, are merged first, so uv seams and split normals do not count as
+// boundaries. The sign of the mean curvature follows the given normals, or the triangle
+// winding when there are none. Vertices on the open boundary of the mesh take the average of
+// their interior neighbors.
+pub fn mesh_curvature(positions:&[[f32; 3]], normals:Option<&[[f32; 3]]>, indices:&[u32]) -> Vec<Curvature> {
+    // merge vertices by position, numbering the merged vertices in order
+    let weld = weld_by_position(positions, weld_tolerance(positions));
+    let mut id = vec![usize::MAX; positions.len()];
+    let mut welded: Vec<usize> = vec![];
+    let mut points: Vec<Vector3<f32>> = vec![];
+    for (i, &first) in weld.iter().enumerate() {
+        if first as usize == i {
+            id[i] = points.len();
+            points.push(Vector3::from(positions[i]));
+        }
+        welded.push(id[first as usize]);
+    }
+    let m = points.len();
+
+    let mut area = vec![0.0f32; m];
+    let mut angles = vec![0.0f32; m];
+    let mut laplacian = vec![Vector3::zero(); m];
+    let mut face_normals = vec![Vector3::zero(); m];
+    let mut edges: HashMap<(usize, usize), u32> = HashMap::new();
+    let mut neighbors: Vec<Vec<usize>> = vec![vec![]; m];
+    for tri in indices.chunks(3) {
+        let c = [welded[tri[0] as usize], welded[tri[1] as usize], welded[tri[2] as usize]];
+        if c[0] == c[1] || c[1] == c[2] || c[2] == c[0] {
+            continue;
+        }
+        let p = c.map(|i| points[i]);
+        let n = (p[1] - p[0]).cross(p[2] - p[0]);
+        let a = 0.5 * n.magnitude();
+        if a == 0.0 {
+            continue;
+        }
+        for k in 0..3 {
+            let (i, j, o) = (c[k], c[(k + 1) % 3], c[(k + 2) % 3]);
+            area[i] += a / 3.0;
+            face_normals[i] += n;
+            *edges.entry((i.min(j), i.max(j))).or_insert(0) += 1;
+            neighbors[i].push(j);
+            neighbors[j].push(i);
+
+            // interior angle at i
+            let (e1, e2) = (points[j] - points[i], points[o] - points[i]);
+            angles[i] += e1.angle(e2).0;
+
+            // the angle at o weights the edge i-j: cot = cos/sin = dot/|cross|
+            let (f1, f2) = (points[i] - points[o], points[j] - points[o]);
+            let cot = f1.dot(f2) / f1.cross(f2).magnitude();
+            laplacian[i] += (points[j] - points[i]) * (0.5 * cot);
+            laplacian[j] += (points[i] - points[j]) * (0.5 * cot);
+        }
+    }
+
+    let mut boundary = vec![false; m];
+    for (&(i, j), &count) in &edges {
+        if count == 1 {
+            boundary[i] = true;
+            boundary[j] = true;
+        }
+    }
+
+    // vertex normals for the sign of the mean curvature
+    let mut vertex_normals = face_normals;
+    if let Some(normals) = normals {
+        vertex_normals = vec![Vector3::zero(); m];
+        for (i, n) in normals.iter().enumerate() {
+            vertex_normals[welded[i]] += Vector3::from(*n);
+        }
+    }
+
+    let mut curvatures: Vec<Curvature> = (0..m).map(|i| {
+        if boundary[i] || area[i] == 0.0 {
+            return Curvature::default();
+        }
+        let k = (2.0 * PI - angles[i]) / area[i];
+        // the Laplacian of the position is -2 H n
+        let h = laplacian[i] / area[i];
+        let n = vertex_normals[i];
+        let mean = if n.magnitude2() > 0.0 { -0.5 * h.dot(n.normalize()) } else { 0.5 * h.magnitude() };
+        Curvature::from_gaussian_mean(k, mean)
+    }).collect();
+
+    for i in 0..m {
+        if !boundary[i] {
+            continue;
+        }
+        let interior: Vec<Curvature> = neighbors[i].iter()
+            .filter(|&&j| !boundary[j]).map(|&j| curvatures[j]).collect();
+        if !interior.is_empty() {
+            let count = interior.len() as f32;
+            let sum = |f:fn(&Curvature) -> f32| interior.iter().map(f).sum::<f32>() / count;
+            curvatures[i] = Curvature {
+                gaussian: sum(|c| c.gaussian),
+                mean: sum(|c| c.mean),
+                k1: sum(|c| c.k1),
+                k2: sum(|c| c.k2),
+            };
+        }
+    }
+    welded.iter().map(|&i| curvatures[i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex_data::create_torus_data;
+
+    // The uv seams of the torus repeat positions up to rounding; welded, the mesh is closed and
+    // every vertex, seams included, gets the analytic curvatures of the torus with tube angle
+    // v: K = cos v / (r (R + r cos v)) and H = (R + 2 r cos v) / (2 r (R + r cos v)).
+    #[test]
+    fn torus() {
+        let (big_r, r) = (1.5, 0.5);
+        let mesh = create_torus_data(big_r, r, 32, 16, [1.0, 1.0]);
+        let curvatures = mesh_curvature(&mesh.positions, mesh.normals.as_deref(), &mesh.indices.to_u32());
+        let (mut k_error, mut h_error) = (0.0f32, 0.0f32);
+        for (p, c) in mesh.positions.iter().zip(&curvatures) {
+            let rho = (p[0] * p[0] + p[2] * p[2]).sqrt();
+            let cos_v = ((rho - big_r) / r).clamp(-1.0, 1.0);
+            let gaussian = cos_v / (r * (big_r + r * cos_v));
+            let mean = (big_r + 2.0 * r * cos_v) / (2.0 * r * (big_r + r * cos_v));
+            k_error = k_error.max((c.gaussian - gaussian).abs());
+            h_error = h_error.max((c.mean - mean).abs());
+        }
+        assert!(k_error < 0.05, "gaussian curvature off by {}", k_error);
+        assert!(h_error < 0.05, "mean curvature off by {}", h_error);
+    }
+}
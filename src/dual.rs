@@ -0,0 +1,194 @@
+#![allow(dead_code)]
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+// Dual number for forward-mode differentiation of surface functions in two variables:
+// re is the value, du and dv are its partial derivatives with respect to u and v.
+// Evaluating a function on Dual::u(u) and Dual::v(v) gives the point and both partials.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Dual {
+    pub re: f32,
+    pub du: f32,
+    pub dv: f32,
+}
+
+impl Dual {
+    pub fn new(re:f32, du:f32, dv:f32) -> Self {
+        Self { re, du, dv }
+    }
+
+    pub fn constant(re:f32) -> Self {
+        Self::new(re, 0.0, 0.0)
+    }
+
+    // the variable u, with du/du = 1
+    pub fn u(re:f32) -> Self {
+        Self::new(re, 1.0, 0.0)
+    }
+
+    // the variable v, with dv/dv = 1
+    pub fn v(re:f32) -> Self {
+        Self::new(re, 0.0, 1.0)
+    }
+
+    // applies the chain rule for a function with value f and derivative df at self.re
+    fn chain(self, f:f32, df:f32) -> Self {
+        Self::new(f, df * self.du, df * self.dv)
+    }
+
+    pub fn sin(self) -> Self {
+        self.chain(self.re.sin(), self.re.cos())
+    }
+
+    pub fn cos(self) -> Self {
+        self.chain(self.re.cos(), -self.re.sin())
+    }
+
+    pub fn tan(self) -> Self {
+        let t = self.re.tan();
+        self.chain(t, 1.0 + t * t)
+    }
+
+    pub fn atan(self) -> Self {
+        self.chain(self.re.atan(), 1.0 / (1.0 + self.re * self.re))
+    }
+
+    pub fn asin(self) -> Self {
+        self.chain(self.re.asin(), 1.0 / (1.0 - self.re * self.re).sqrt())
+    }
+
+    pub fn acos(self) -> Self {
+        self.chain(self.re.acos(), -1.0 / (1.0 - self.re * self.re).sqrt())
+    }
+
+    // angle of the point (x, self), like f32::atan2
+    pub fn atan2(self, x:Dual) -> Self {
+        let d = self.re * self.re + x.re * x.re;
+        Self::new(
+            self.re.atan2(x.re), 
+            (x.re * self.du - self.re * x.du) / d, 
+            (x.re * self.dv - self.re * x.dv) / d,
+        )
+    }
+
+    pub fn sinh(self) -> Self {
+        self.chain(self.re.sinh(), self.re.cosh())
+    }
+
+    pub fn cosh(self) -> Self {
+        self.chain(self.re.cosh(), self.re.sinh())
+    }
+
+    pub fn tanh(self) -> Self {
+        let t = self.re.tanh();
+        self.chain(t, 1.0 - t * t)
+    }
+
+    pub fn exp(self) -> Self {
+        let e = self.re.exp();
+        self.chain(e, e)
+    }
+
+    pub fn ln(self) -> Self {
+        self.chain(self.re.ln(), 1.0 / self.re)
+    }
+
+    pub fn sqrt(self) -> Self {
+        let s = self.re.sqrt();
+        self.chain(s, 0.5 / s)
+    }
+
+    pub fn powf(self, n:f32) -> Self {
+        self.chain(self.re.powf(n), n * self.re.powf(n - 1.0))
+    }
+
+    // power with a variable exponent; constant exponents use powf
+    pub fn pow(self, n:Dual) -> Self {
+        if n.du == 0.0 && n.dv == 0.0 {
+            self.powf(n.re)
+        } else {
+            (n * self.ln()).exp()
+        }
+    }
+
+    pub fn abs(self) -> Self {
+        self.chain(self.re.abs(), self.re.signum())
+    }
+}
+
+impl From<f32> for Dual {
+    fn from(re:f32) -> Self {
+        Self::constant(re)
+    }
+}
+
+impl PartialEq<f32> for Dual {
+    fn eq(&self, other:&f32) -> bool {
+        self.re == *other
+    }
+}
+
+// comparisons use the value only, so branches in surface functions work unchanged
+impl PartialOrd<f32> for Dual {
+    fn partial_cmp(&self, other:&f32) -> Option<std::cmp::Ordering> {
+        self.re.partial_cmp(other)
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+    fn neg(self) -> Dual {
+        Dual::new(-self.re, -self.du, -self.dv)
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+    fn add(self, b:Dual) -> Dual {
+        Dual::new(self.re + b.re, self.du + b.du, self.dv + b.dv)
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+    fn sub(self, b:Dual) -> Dual {
+        Dual::new(self.re - b.re, self.du - b.du, self.dv - b.dv)
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+    fn mul(self, b:Dual) -> Dual {
+        Dual::new(self.re * b.re, self.du * b.re + self.re * b.du, self.dv * b.re + self.re * b.dv)
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+    fn div(self, b:Dual) -> Dual {
+        let d = b.re * b.re;
+        Dual::new(self.re / b.re, (self.du * b.re - self.re * b.du) / d, (self.dv * b.re - self.re * b.dv) / d)
+    }
+}
+
+// mixed operations with f32 constants, in both orders
+macro_rules! impl_scalar_ops {
+    ($($op:ident, $f:ident);*) => {
+        $(
+            impl $op<f32> for Dual {
+                type Output = Dual;
+                fn $f(self, b:f32) -> Dual {
+                    $op::$f(self, Dual::constant(b))
+                }
+            }
+
+            impl $op<Dual> for f32 {
+                type Output = Dual;
+                fn $f(self, b:Dual) -> Dual {
+                    $op::$f(Dual::constant(self), b)
+                }
+            }
+        )*
+    };
+}
+
+impl_scalar_ops!(Add, add; Sub, sub; Mul, mul; Div, div);
@@ -0,0 +1,709 @@
+#![allow(dead_code)]
+use std::f32::consts::{E, PI};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::sync::Arc;
+use super::dual::Dual;
+use super::surface_registry::{detect_seams, Domain, ParametricSurface, Seam};
+use anyhow::{anyhow, bail, Result};
+
+// Small expression language for defining surfaces at runtime, e.g.
+//   "y = sin(r*t)/r"                                   (simple surface, inputs x, z, t, r)
+//   "x = cos(u)*sin(v); y = cos(v); z = sin(u)*sin(v)"  (parametric surface, inputs u, v, t)
+//   "f = x*x + y*y + z*z - 1"                          (implicit surface, inputs x, y, z, t)
+// A program is a list of statements separated by ';' or new lines. Each statement assigns an
+// expression to a name, which can be used by the later statements. Expressions support
+// + - * / ^ (power), parentheses, the constants pi and e, and the functions listed in Func.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Func {
+    Sin, Cos, Tan, Asin, Acos, Atan, Atan2, Sinh, Cosh, Tanh,
+    Exp, Ln, Sqrt, Abs, Pow, Min, Max,
+}
+
+impl Func {
+    fn from_name(name:&str) -> Option<(Func, usize)> {
+        let f = match name {
+            "sin" => (Func::Sin, 1),
+            "cos" => (Func::Cos, 1),
+            "tan" => (Func::Tan, 1),
+            "asin" => (Func::Asin, 1),
+            "acos" => (Func::Acos, 1),
+            "atan" => (Func::Atan, 1),
+            "atan2" => (Func::Atan2, 2),
+            "sinh" => (Func::Sinh, 1),
+            "cosh" => (Func::Cosh, 1),
+            "tanh" => (Func::Tanh, 1),
+            "exp" => (Func::Exp, 1),
+            "ln" | "log" => (Func::Ln, 1),
+            "sqrt" => (Func::Sqrt, 1),
+            "abs" => (Func::Abs, 1),
+            "pow" => (Func::Pow, 2),
+            "min" => (Func::Min, 2),
+            "max" => (Func::Max, 2),
+            _ => return None,
+        };
+        Some(f)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(f32),
+    Var(usize),  // slot index
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+// Number type a program can be evaluated with: f32 for values, Dual for exact derivatives.
+pub trait Value: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+    + Div<Output = Self> + Neg<Output = Self> {
+    fn constant(x:f32) -> Self;
+    fn call(f:Func, a:Self, b:Self) -> Self;
+}
+
+impl Value for f32 {
+    fn constant(x:f32) -> Self {
+        x
+    }
+
+    fn call(f:Func, a:f32, b:f32) -> f32 {
+        match f {
+            Func::Sin => a.sin(),
+            Func::Cos => a.cos(),
+            Func::Tan => a.tan(),
+            Func::Asin => a.asin(),
+            Func::Acos => a.acos(),
+            Func::Atan => a.atan(),
+            Func::Atan2 => a.atan2(b),
+            Func::Sinh => a.sinh(),
+            Func::Cosh => a.cosh(),
+            Func::Tanh => a.tanh(),
+            Func::Exp => a.exp(),
+            Func::Ln => a.ln(),
+            Func::Sqrt => a.sqrt(),
+            Func::Abs => a.abs(),
+            Func::Pow => a.powf(b),
+            Func::Min => a.min(b),
+            Func::Max => a.max(b),
+        }
+    }
+}
+
+impl Value for Dual {
+    fn constant(x:f32) -> Self {
+        Dual::constant(x)
+    }
+
+    fn call(f:Func, a:Dual, b:Dual) -> Dual {
+        match f {
+            Func::Sin => a.sin(),
+            Func::Cos => a.cos(),
+            Func::Tan => a.tan(),
+            Func::Asin => a.asin(),
+            Func::Acos => a.acos(),
+            Func::Atan => a.atan(),
+            Func::Atan2 => a.atan2(b),
+            Func::Sinh => a.sinh(),
+            Func::Cosh => a.cosh(),
+            Func::Tanh => a.tanh(),
+            Func::Exp => a.exp(),
+            Func::Ln => a.ln(),
+            Func::Sqrt => a.sqrt(),
+            Func::Abs => a.abs(),
+            Func::Pow => a.pow(b),
+            Func::Min => if b.re < a.re { b } else { a },
+            Func::Max => if b.re > a.re { b } else { a },
+        }
+    }
+}
+
+impl Expr {
+    fn eval<T: Value>(&self, slots:&[T]) -> T {
+        match self {
+            Expr::Num(x) => T::constant(*x),
+            Expr::Var(i) => slots[*i],
+            Expr::Neg(a) => -a.eval(slots),
+            Expr::Add(a, b) => a.eval(slots) + b.eval(slots),
+            Expr::Sub(a, b) => a.eval(slots) - b.eval(slots),
+            Expr::Mul(a, b) => a.eval(slots) * b.eval(slots),
+            Expr::Div(a, b) => a.eval(slots) / b.eval(slots),
+            Expr::Pow(a, b) => T::call(Func::Pow, a.eval(slots), b.eval(slots)),
+            Expr::Call(f, args) => {
+                let a = args[0].eval(slots);
+                let b = if args.len() > 1 { args[1].eval(slots) } else { a };
+                T::call(*f, a, b)
+            }
+        }
+    }
+}
+
+// A parsed program. Variables live in slots: the inputs first, then every assigned name.
+#[derive(Debug, Clone)]
+pub struct Program {
+    names: Vec<String>,
+    statements: Vec<(usize, Expr)>,
+    inputs: usize,
+}
+
+impl Program {
+    // Parses source with the given input variables. A single statement without '=' is
+    // accepted as an assignment to default_output, if one is given.
+    pub fn parse(source:&str, inputs:&[&str], default_output:Option<&str>) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        let mut p = Parser {
+            source,
+            tokens,
+            pos: 0,
+            names: inputs.iter().map(|s| s.to_string()).collect(),
+        };
+        let mut statements = vec![];
+        loop {
+            while p.peek() == &Token::Separator {
+                p.pos += 1;
+            }
+            if p.peek() == &Token::End {
+                break;
+            }
+            let assignment = matches!(p.peek(), Token::Ident(_)) && p.peek_at(1) == &Token::Assign;
+            let slot = if assignment {
+                let name = match p.next() {
+                    Token::Ident(name) => name,
+                    _ => unreachable!(),
+                };
+                // the constants would silently win over a variable of the same name
+                if name == "pi" || name == "e" {
+                    p.pos -= 1;
+                    return Err(p.error(&format!("cannot assign to the constant '{}'", name)));
+                }
+                p.pos += 1;
+                Some(name)
+            } else {
+                None
+            };
+            let expr = p.expr()?;
+            let name = match (slot, default_output) {
+                (Some(name), _) => name,
+                (None, Some(out)) if statements.is_empty() && p.at_end() => out.to_string(),
+                (None, _) => return Err(p.error("expected an assignment such as 'y = ...'")),
+            };
+            statements.push((p.slot(&name), expr));
+            if !matches!(p.peek(), Token::Separator | Token::End) {
+                return Err(p.error("expected ';' or the end of the input"));
+            }
+        }
+        if statements.is_empty() {
+            bail!("empty expression");
+        }
+        Ok(Self { names: p.names, statements, inputs: inputs.len() })
+    }
+
+    pub fn slot(&self, name:&str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    // true if the program assigns the name
+    pub fn assigns(&self, name:&str) -> bool {
+        match self.slot(name) {
+            Some(i) => self.statements.iter().any(|(s, _)| *s == i),
+            None => false,
+        }
+    }
+
+    // Runs the statements and returns all slots; inputs must match the parse inputs.
+    pub fn eval<T: Value>(&self, inputs:&[T]) -> Vec<T> {
+        let mut slots = inputs[..self.inputs].to_vec();
+        slots.resize(self.names.len(), T::constant(0.0));
+        for (slot, expr) in &self.statements {
+            slots[*slot] = expr.eval(&slots);
+        }
+        slots
+    }
+}
+
+// region: lexer and parser
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f32),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+    Assign,
+    Separator,
+    End,
+}
+
+fn error_at(source:&str, pos:usize, msg:&str) -> anyhow::Error {
+    let col = source[..pos.min(source.len())].chars().count();
+    anyhow!("{} at column {}\n  {}\n  {}^", msg, col + 1, source.replace('\n', " "), " ".repeat(col))
+}
+
+fn tokenize(source:&str) -> Result<Vec<(Token, usize)>> {
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let (pos, c) = chars[i];
+        if c == '\n' || c == ';' {
+            tokens.push((Token::Separator, pos));
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].1.is_ascii_digit() || chars[i].1 == '.') {
+                i += 1;
+            }
+            // exponent, e.g. 1.5e-3
+            if i + 1 < chars.len() && (chars[i].1 == 'e' || chars[i].1 == 'E') {
+                let mut j = i + 1;
+                if chars[j].1 == '+' || chars[j].1 == '-' {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].1.is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].1.is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let end = if i < chars.len() { chars[i].0 } else { source.len() };
+            let text = &source[pos..end];
+            let x = text.parse::<f32>()
+                .map_err(|_| error_at(source, chars[start].0, &format!("invalid number '{}'", text)))?;
+            tokens.push((Token::Num(x), pos));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+                i += 1;
+            }
+            let end = if i < chars.len() { chars[i].0 } else { source.len() };
+            tokens.push((Token::Ident(source[pos..end].to_string()), pos));
+        } else {
+            let token = match c {
+                '+' | '-' | '*' | '/' | '^' => Token::Op(c),
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                '=' => Token::Assign,
+                _ => return Err(error_at(source, pos, &format!("unexpected character '{}'", c))),
+            };
+            tokens.push((token, pos));
+            i += 1;
+        }
+    }
+    tokens.push((Token::End, source.len()));
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    names: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, k:usize) -> &Token {
+        let i = (self.pos + k).min(self.tokens.len() - 1);
+        &self.tokens[i].0
+    }
+
+    fn next(&mut self) -> Token {
+        let t = self.peek().clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        t
+    }
+
+    fn at_end(&self) -> bool {
+        self.tokens[self.pos..].iter().all(|(t, _)| matches!(t, Token::Separator | Token::End))
+    }
+
+    fn error(&self, msg:&str) -> anyhow::Error {
+        error_at(self.source, self.tokens[self.pos].1, msg)
+    }
+
+    fn expect(&mut self, token:Token, what:&str) -> Result<()> {
+        if self.peek() == &token {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}", what)))
+        }
+    }
+
+    fn slot(&mut self, name:&str) -> usize {
+        match self.names.iter().position(|n| n == name) {
+            Some(i) => i,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr> {
+        let mut a = self.term()?;
+        while let Token::Op(op @ ('+' | '-')) = *self.peek() {
+            self.pos += 1;
+            let b = self.term()?;
+            a = if op == '+' { Expr::Add(a.into(), b.into()) } else { Expr::Sub(a.into(), b.into()) };
+        }
+        Ok(a)
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Expr> {
+        let mut a = self.unary()?;
+        while let Token::Op(op @ ('*' | '/')) = *self.peek() {
+            self.pos += 1;
+            let b = self.unary()?;
+            a = if op == '*' { Expr::Mul(a.into(), b.into()) } else { Expr::Div(a.into(), b.into()) };
+        }
+        Ok(a)
+    }
+
+    // unary := ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Token::Op('-') => {
+                self.pos += 1;
+                Ok(Expr::Neg(self.unary()?.into()))
+            }
+            Token::Op('+') => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    // power := primary ('^' unary)?, so 2^-x works and -x^2 is -(x^2)
+    fn power(&mut self) -> Result<Expr> {
+        let a = self.primary()?;
+        if self.peek() == &Token::Op('^') {
+            self.pos += 1;
+            let b = self.unary()?;
+            return Ok(Expr::Pow(a.into(), b.into()));
+        }
+        Ok(a)
+    }
+
+    // primary := number | name | function '(' args ')' | '(' expr ')'
+    fn primary(&mut self) -> Result<Expr> {
+        let start = self.pos;
+        match self.next() {
+            Token::Num(x) => Ok(Expr::Num(x)),
+            Token::LParen => {
+                let e = self.expr()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(e)
+            }
+            Token::Ident(name) => {
+                if self.peek() == &Token::LParen {
+                    let (f, arity) = match Func::from_name(&name) {
+                        Some(f) => f,
+                        None => {
+                            self.pos = start;
+                            return Err(self.error(&format!("unknown function '{}'", name)));
+                        }
+                    };
+                    self.pos += 1;
+                    let mut args = vec![self.expr()?];
+                    while self.peek() == &Token::Comma {
+                        self.pos += 1;
+                        args.push(self.expr()?);
+                    }
+                    self.expect(Token::RParen, "')'")?;
+                    if args.len() != arity {
+                        self.pos = start;
+                        return Err(self.error(&format!("'{}' takes {} argument(s), found {}",
+                            name, arity, args.len())));
+                    }
+                    return Ok(Expr::Call(f, args));
+                }
+                match name.as_str() {
+                    "pi" => Ok(Expr::Num(PI)),
+                    "e" => Ok(Expr::Num(E)),
+                    _ => match self.names.iter().position(|n| *n == name) {
+                        Some(i) => Ok(Expr::Var(i)),
+                        None => {
+                            self.pos = start;
+                            Err(self.error(&format!("unknown variable '{}'", name)))
+                        }
+                    },
+                }
+            }
+            Token::End | Token::Separator => {
+                self.pos = start;
+                Err(self.error("unexpected end of expression"))
+            }
+            _ => {
+                self.pos = start;
+                Err(self.error("expected a number, name or '('"))
+            }
+        }
+    }
+}
+// endregion: lexer and parser
+
+// region: surfaces
+// Height function y(x, z, t) for ISimpleSurface. The inputs are x, z, t and r = sqrt(x² + z²);
+// the source may be a bare expression for y, or statements assigning y (and optionally x, z).
+#[derive(Debug, Clone)]
+pub struct SimpleExpr {
+    program: Arc<Program>,
+}
+
+impl SimpleExpr {
+    // Domain (xmin, xmax, zmin, zmax) and aspect ratio ISimpleSurface uses for expressions
+    // unless overridden; they suit radial functions like sin(r*t)/r.
+    pub const DOMAIN: Domain = (-8.0, 8.0, -8.0, 8.0);
+    pub const ASPECT_RATIO: f32 = 0.5;
+
+    pub fn parse(source:&str) -> Result<Self> {
+        let program = Program::parse(source, &["x", "z", "t", "r"], Some("y"))?;
+        if !program.assigns("y") {
+            bail!("expression does not assign y");
+        }
+        Ok(Self { program: Arc::new(program) })
+    }
+
+    pub fn eval(&self, x:f32, z:f32, t:f32) -> [f32; 3] {
+        let s = self.program.eval(&[x, z, t, (x * x + z * z).sqrt()]);
+        let get = |name:&str| s[self.program.slot(name).unwrap()];
+        [get("x"), get("y"), get("z")]
+    }
+}
+
+// Implicit function f(x, y, z, t) for IImplicitSurface; a bare expression or statements
+// assigning f.
+#[derive(Debug, Clone)]
+pub struct ImplicitExpr {
+    program: Arc<Program>,
+    f: usize,
+}
+
+impl ImplicitExpr {
+    pub fn parse(source:&str) -> Result<Self> {
+        let program = Program::parse(source, &["x", "y", "z", "t"], Some("f"))?;
+        if !program.assigns("f") {
+            bail!("expression does not assign f");
+        }
+        let f = program.slot("f").unwrap();
+        Ok(Self { program: Arc::new(program), f })
+    }
+
+    pub fn eval(&self, x:f32, y:f32, z:f32, t:f32) -> f32 {
+        self.program.eval(&[x, y, z, t])[self.f]
+    }
+}
+
+// Parametric surface defined by statements assigning x, y and z from u, v and t. It can be
+// registered in a SurfaceRegistry like the built-in surfaces; derivatives are exact because
+// the program is also evaluated with dual numbers. The statements may also assign umin, umax,
+// vmin and vmax to declare the domain, e.g. "vmin = -1; vmax = 1"; bounds left out come from
+// the domain given to parse. They are evaluated once, at t = 0, and must not depend on u or v.
+#[derive(Debug, Clone)]
+pub struct ExprSurface {
+    name: String,
+    domain: Domain,
+    program: Arc<Program>,
+    xyz: [usize; 3],
+    pub t: f32,
+}
+
+impl ExprSurface {
+    // Domain IParametricSurface uses for expressions that do not declare their own: one turn
+    // in both u and v, as on the torus.
+    pub const DOMAIN: Domain = (0.0, 2.0 * PI, 0.0, 2.0 * PI);
+
+    pub fn parse(name:&str, domain:Domain, source:&str) -> Result<Self> {
+        let program = Program::parse(source, &["u", "v", "t"], None)?;
+        let mut xyz = [0; 3];
+        for (k, c) in ["x", "y", "z"].iter().enumerate() {
+            if !program.assigns(c) {
+                bail!("expression does not assign {}", c);
+            }
+            xyz[k] = program.slot(c).unwrap();
+        }
+
+        // the derivatives tell whether a bound depends on u or v; 1 is less likely than 0 to
+        // sit on a stationary point such as that of u*u
+        let s = program.eval(&[Dual::u(1.0), Dual::v(1.0), Dual::constant(0.0)]);
+        let mut bounds = [domain.0, domain.1, domain.2, domain.3];
+        for (k, b) in ["umin", "umax", "vmin", "vmax"].iter().enumerate() {
+            if !program.assigns(b) {
+                continue;
+            }
+            let x = s[program.slot(b).unwrap()];
+            if x.du != 0.0 || x.dv != 0.0 {
+                bail!("{} must not depend on u or v", b);
+            }
+            if !x.re.is_finite() {
+                bail!("{} is not a finite number", b);
+            }
+            bounds[k] = x.re;
+        }
+        if bounds[0] >= bounds[1] || bounds[2] >= bounds[3] {
+            bail!("empty domain: u in [{}, {}], v in [{}, {}]", bounds[0], bounds[1], bounds[2], bounds[3]);
+        }
+        let domain = (bounds[0], bounds[1], bounds[2], bounds[3]);
+        Ok(Self { name: name.to_string(), domain, program: Arc::new(program), xyz, t: 0.0 })
+    }
+
+    // the same surface at time t, without parsing again
+    pub fn at_time(&self, t:f32) -> Self {
+        Self { t, ..self.clone() }
+    }
+}
+
+impl ParametricSurface for ExprSurface {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn evaluate(&self, u:f32, v:f32) -> [f32; 3] {
+        let s = self.program.eval(&[u, v, self.t]);
+        self.xyz.map(|i| s[i])
+    }
+
+    fn domain(&self) -> Domain {
+        self.domain
+    }
+
+    fn derivatives(&self, u:f32, v:f32) -> Option<[[f32; 3]; 2]> {
+        let s = self.program.eval(&[Dual::u(u), Dual::v(v), Dual::constant(self.t)]);
+        Some([self.xyz.map(|i| s[i].du), self.xyz.map(|i| s[i].dv)])
+    }
+
+    // an expression cannot declare how it closes up, so the seams are found numerically
+    fn seams(&self, d:Domain) -> [Seam; 2] {
+        detect_seams(&|u, v| self.evaluate(u, v), d)
+    }
+}
+// endregion: surfaces
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn y(source:&str) -> f32 {
+        SimpleExpr::parse(source).unwrap().eval(0.0, 0.0, 0.0)[1]
+    }
+
+    fn parse_error(source:&str) -> String {
+        match Program::parse(source, &["x", "t"], None) {
+            Ok(_) => panic!("'{}' parsed", source),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(y("1 + 2*3"), 7.0);
+        assert_eq!(y("(1 + 2)*3"), 9.0);
+        assert_eq!(y("8/4/2"), 1.0);
+        assert_eq!(y("2 - 3 - 4"), -5.0);
+        assert_eq!(y("-2^2"), -4.0);
+        assert_eq!(y("2^-1"), 0.5);
+        assert_eq!(y("2^3^2"), 512.0);
+        assert_eq!(y("1.5e-3*2e3"), 3.0);
+    }
+
+    #[test]
+    fn functions_and_constants() {
+        assert!((y("sin(pi/2) + cos(0)") - 2.0).abs() < 1e-6);
+        assert!((y("ln(e)") - 1.0).abs() < 1e-6);
+        assert_eq!(y("atan2(0, -1)"), PI);
+        assert_eq!(y("min(3, max(1, 2))"), 2.0);
+        assert_eq!(y("pow(2, 10)"), 1024.0);
+        assert_eq!(y("abs(-3) + sqrt(16)"), 7.0);
+    }
+
+    // inputs, statements that use earlier names, and x and z reassigned by a simple surface
+    #[test]
+    fn statements() {
+        let e = SimpleExpr::parse("a = x + z; y = a*t\nz = 2*z").unwrap();
+        assert_eq!(e.eval(1.0, 2.0, 3.0), [1.0, 9.0, 4.0]);
+        let e = SimpleExpr::parse("r").unwrap();
+        assert_eq!(e.eval(3.0, 4.0, 0.0), [3.0, 5.0, 4.0]);
+        let f = ImplicitExpr::parse("x*x + y*y + z*z - t").unwrap();
+        assert_eq!(f.eval(1.0, 2.0, 2.0, 9.0), 0.0);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse_error("y = ").contains("unexpected end of expression"));
+        assert!(parse_error("y = x + q").contains("unknown variable 'q'"));
+        assert!(parse_error("y = foo(x)").contains("unknown function 'foo'"));
+        assert!(parse_error("y = atan2(x)").contains("'atan2' takes 2 argument(s), found 1"));
+        assert!(parse_error("y = (x + 1").contains("expected ')'"));
+        assert!(parse_error("y = x $ 2").contains("unexpected character '$'"));
+        assert!(parse_error("y = 1.2.3").contains("invalid number '1.2.3'"));
+        assert!(parse_error("y = x 2").contains("expected ';' or the end of the input"));
+        assert!(parse_error("x + 1").contains("expected an assignment"));
+        assert!(parse_error(" ; \n").contains("empty expression"));
+        // a later name is not visible to the statements before it
+        assert!(parse_error("y = a; a = 1").contains("unknown variable 'a'"));
+    }
+
+    // the message points at the offending token
+    #[test]
+    fn error_column() {
+        let e = parse_error("y = x + q");
+        assert!(e.starts_with("unknown variable 'q' at column 9"), "{}", e);
+        assert!(e.ends_with(&format!("\n  y = x + q\n  {}^", " ".repeat(8))), "{}", e);
+    }
+
+    #[test]
+    fn constants_cannot_be_assigned() {
+        assert!(parse_error("pi = 3; y = pi").contains("cannot assign to the constant 'pi'"));
+        assert!(parse_error("e = 2\ny = x*e").contains("cannot assign to the constant 'e'"));
+    }
+
+    #[test]
+    fn missing_outputs() {
+        assert!(SimpleExpr::parse("x = 1").unwrap_err().to_string().contains("does not assign y"));
+        assert!(ImplicitExpr::parse("g = x").unwrap_err().to_string().contains("does not assign f"));
+        let e = ExprSurface::parse("s", ExprSurface::DOMAIN, "x = u; y = v").unwrap_err();
+        assert!(e.to_string().contains("does not assign z"));
+    }
+
+    #[test]
+    fn surface_derivatives() {
+        let s = ExprSurface::parse("s", ExprSurface::DOMAIN, "x = cos(u)*v; y = v^2; z = sin(u)*t").unwrap()
+            .at_time(2.0);
+        let (u, v) = (0.7, 1.3);
+        assert_eq!(s.evaluate(u, v), [u.cos() * v, v * v, u.sin() * 2.0]);
+        let [pu, pv] = s.derivatives(u, v).unwrap();
+        let expected = [[-u.sin() * v, 0.0, u.cos() * 2.0], [u.cos(), 2.0 * v, 0.0]];
+        for (d, e) in [pu, pv].iter().zip(expected) {
+            assert!((0..3).all(|k| (d[k] - e[k]).abs() < 1e-5), "{:?}, expected {:?}", d, e);
+        }
+    }
+
+    #[test]
+    fn declared_domain() {
+        let source = "x = u; y = v; z = 0; umin = -pi; umax = pi; vmax = 2*3";
+        let s = ExprSurface::parse("s", ExprSurface::DOMAIN, source).unwrap();
+        assert_eq!(s.domain(), (-PI, PI, 0.0, 6.0));
+        // bounds are constants of the surface
+        let bad = ExprSurface::parse("s", ExprSurface::DOMAIN, "x = u; y = v; z = 0; vmax = 1 + u*u");
+        assert!(bad.unwrap_err().to_string().contains("vmax must not depend on u or v"));
+        let bad = ExprSurface::parse("s", ExprSurface::DOMAIN, "x = u; y = v; z = 0; umin = 7");
+        assert!(bad.unwrap_err().to_string().contains("empty domain"));
+    }
+}
@@ -0,0 +1,597 @@
+#![allow(dead_code)]
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use cgmath::*;
+use serde_json::{json, Value};
+use super::mesh::{Indices, Material, Mesh, TextureSource};
+
+// glTF component types and buffer view targets
+const FLOAT: u32 = 5126;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// region: export
+// Collects the binary chunk and the buffer views / accessors that point into it.
+#[derive(Default)]
+struct GlbBuilder {
+    bin: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    images: Vec<Value>,
+    textures: Vec<Value>,
+}
+
+impl GlbBuilder {
+    fn push_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        while !self.bin.len().is_multiple_of(4) {
+            self.bin.push(0);
+        }
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.bin.extend_from_slice(bytes);
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    fn push_floats<const N: usize>(&mut self, data: &[[f32; N]], with_bounds: bool) -> usize {
+        let bytes: Vec<u8> = data.iter().flat_map(|v| v.iter().flat_map(|f| f.to_le_bytes())).collect();
+        let view = self.push_view(&bytes, Some(ARRAY_BUFFER));
+        let ty = match N {
+            2 => "VEC2",
+            3 => "VEC3",
+            _ => "VEC4",
+        };
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": data.len(),
+            "type": ty,
+        });
+        // positions require min and max
+        if with_bounds {
+            let mut min = [f32::MAX; N];
+            let mut max = [f32::MIN; N];
+            for v in data {
+                for k in 0..N {
+                    min[k] = min[k].min(v[k]);
+                    max[k] = max[k].max(v[k]);
+                }
+            }
+            accessor["min"] = json!(min.to_vec());
+            accessor["max"] = json!(max.to_vec());
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &Indices) -> usize {
+        let view = self.push_view(indices.as_bytes(), Some(ELEMENT_ARRAY_BUFFER));
+        let component_type = match indices {
+            Indices::U16(_) => UNSIGNED_SHORT,
+            Indices::U32(_) => UNSIGNED_INT,
+        };
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": component_type,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    // embeds an image and returns the index of a texture that samples it
+    fn push_texture(&mut self, source: &TextureSource) -> Result<usize> {
+        let bytes = source.bytes()?;
+        let mime = match source {
+            TextureSource::Embedded { mime_type, .. } => mime_type.clone(),
+            TextureSource::File(path) => {
+                let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+                match ext.as_str() {
+                    "png" => "image/png".to_string(),
+                    "jpg" | "jpeg" => "image/jpeg".to_string(),
+                    _ => bail!("{}: glTF only supports png and jpeg images", path.display()),
+                }
+            }
+        };
+        let view = self.push_view(&bytes, None);
+        self.images.push(json!({ "bufferView": view, "mimeType": mime }));
+        self.textures.push(json!({ "sampler": 0, "source": self.images.len() - 1 }));
+        Ok(self.textures.len() - 1)
+    }
+}
+
+// Maps the Blinn-Phong parameters onto a metallic-roughness material: the diffuse color
+// becomes the base color, the surface is treated as a dielectric, the shininess exponent is
+// converted to roughness with the usual sqrt(2/(n+2)) approximation and the specular color
+// goes into KHR_materials_specular. glTF has no ambient term and no height map slot, so the
+// ambient color and the height texture are stored in the material's extras.
+fn gltf_material(material: &Material, b: &mut GlbBuilder) -> Result<Value> {
+    let d = material.diffuse;
+    let roughness = (2.0 / (material.shininess.max(0.0) + 2.0)).sqrt();
+    let specular = material.specular[0].max(material.specular[1]).max(material.specular[2]);
+    let specular_color = if specular > 0.0 {
+        material.specular.map(|c| c / specular)
+    } else {
+        [1.0; 3]
+    };
+
+    let mut pbr = json!({
+        "baseColorFactor": [d[0], d[1], d[2], 1.0],
+        "metallicFactor": 0.0,
+        "roughnessFactor": roughness,
+    });
+    if let Some(path) = &material.diffuse_texture {
+        pbr["baseColorTexture"] = json!({ "index": b.push_texture(path)? });
+    }
+
+    let mut m = json!({
+        "name": material.name,
+        "pbrMetallicRoughness": pbr,
+        "extensions": {
+            "KHR_materials_specular": {
+                "specularFactor": specular.min(1.0),
+                "specularColorFactor": specular_color,
+            }
+        },
+        "extras": {
+            "ambient": material.ambient,
+            "shininess": material.shininess,
+        },
+    });
+    if let Some(path) = &material.normal_texture {
+        m["normalTexture"] = json!({ "index": b.push_texture(path)? });
+    }
+    if let Some(path) = &material.height_texture {
+        m["extras"]["heightTexture"] = json!({ "index": b.push_texture(path)? });
+    }
+    Ok(m)
+}
+
+// Writes the mesh, its material and all referenced textures into a self-contained binary
+// glTF (.glb). Tangents are included whenever the mesh has normals and uvs; glTF tangents are
+// vec4 with the bitangent sign in w, following MikkTSpace, so a mesh without them is exported
+// as after Mesh::generate_tangents.
+pub fn write_glb<W: Write>(w: &mut W, mesh: &Mesh, material: &Material) -> Result<()> {
+    mesh.validate()?;
+    if mesh.tangents.is_none() && mesh.normals.is_some() && mesh.uvs.is_some() {
+        let mut mesh = mesh.clone();
+        mesh.generate_tangents()?;
+        return write_glb(w, &mesh, material);
+    }
+    let mut b = GlbBuilder::default();
+
+    let mut attributes = json!({ "POSITION": b.push_floats(&mesh.positions, true) });
+    if let Some(normals) = &mesh.normals {
+        attributes["NORMAL"] = json!(b.push_floats(normals, false));
+    }
+    if let Some(uvs) = &mesh.uvs {
+        attributes["TEXCOORD_0"] = json!(b.push_floats(uvs, false));
+    }
+    if let Some(colors) = &mesh.colors {
+        attributes["COLOR_0"] = json!(b.push_floats(colors, false));
+    }
+    if let Some(tangents) = &mesh.tangents {
+        attributes["TANGENT"] = json!(b.push_floats(tangents, false));
+    }
+    // glTF accessors need at least one element, so a mesh without triangles is drawn unindexed
+    let indices = (!mesh.indices.is_empty()).then(|| b.push_indices(&mesh.indices));
+    let material = gltf_material(material, &mut b)?;
+
+    while !b.bin.len().is_multiple_of(4) {
+        b.bin.push(0);
+    }
+    let mut doc = json!({
+        "asset": { "version": "2.0", "generator": "wgpu_textures" },
+        "extensionsUsed": ["KHR_materials_specular"],
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0 }],
+        "meshes": [{
+            "primitives": [{ "attributes": attributes, "material": 0, "mode": 4 }]
+        }],
+        "materials": [material],
+        "buffers": [{ "byteLength": b.bin.len() }],
+        "bufferViews": b.buffer_views,
+        "accessors": b.accessors,
+    });
+    if let Some(indices) = indices {
+        doc["meshes"][0]["primitives"][0]["indices"] = json!(indices);
+    }
+    if !b.textures.is_empty() {
+        // repeat wrapping with trilinear filtering
+        doc["samplers"] = json!([{ "magFilter": 9729, "minFilter": 9987, "wrapS": 10497, "wrapT": 10497 }]);
+        doc["images"] = json!(b.images);
+        doc["textures"] = json!(b.textures);
+    }
+
+    let mut json_bytes = serde_json::to_vec(&doc)?;
+    while !json_bytes.len().is_multiple_of(4) {
+        json_bytes.push(b' ');
+    }
+    let total = 12 + 8 + json_bytes.len() + 8 + b.bin.len();
+    if total > u32::MAX as usize {
+        bail!("glb file would exceed 4 GiB");
+    }
+
+    w.write_all(b"glTF")?;
+    w.write_all(&2u32.to_le_bytes())?;
+    w.write_all(&(total as u32).to_le_bytes())?;
+    w.write_all(&(json_bytes.len() as u32).to_le_bytes())?;
+    w.write_all(b"JSON")?;
+    w.write_all(&json_bytes)?;
+    w.write_all(&(b.bin.len() as u32).to_le_bytes())?;
+    w.write_all(b"BIN\0")?;
+    w.write_all(&b.bin)?;
+    Ok(())
+}
+
+pub fn save_glb<P: AsRef<Path>>(path: P, mesh: &Mesh, material: &Material) -> Result<()> {
+    let path = path.as_ref();
+    let file = File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut w = BufWriter::new(file);
+    write_glb(&mut w, mesh, material)?;
+    w.flush()?;
+    Ok(())
+}
+// endregion: export
+
+// region: import
+// One mesh primitive of a glTF scene. The mesh is kept in the primitive's local space, with
+// TEXCOORD_0 as its uvs and COLOR_0 as its colors; all uv sets are listed in uv_sets and the
+// accumulated node transform is kept separately.
+#[derive(Debug, Clone)]
+pub struct GltfPrimitive {
+    pub name: String,
+    pub mesh: Mesh,
+    pub uv_sets: Vec<Vec<[f32; 2]>>,
+    pub transform: [[f32; 4]; 4],      // column-major world transform of the node
+    pub material_id: Option<usize>,    // index into GltfScene::materials
+}
+
+impl GltfPrimitive {
+    // the mesh with the node transform applied to positions, normals and tangents; a mirroring
+    // transform (negative determinant) also reverses the winding and the tangent handedness,
+    // so that the mesh keeps facing outwards
+    pub fn world_mesh(&self) -> Mesh {
+        let m = Matrix4::from(self.transform);
+        let n = Matrix3::from_cols(m.x.truncate(), m.y.truncate(), m.z.truncate());
+        let n = n.invert().map(|n| n.transpose()).unwrap_or(n);
+        let mut mesh = self.mesh.clone();
+        for p in mesh.positions.iter_mut() {
+            *p = (m * Vector3::from(*p).extend(1.0)).truncate().into();
+        }
+        if let Some(normals) = &mut mesh.normals {
+            for v in normals.iter_mut() {
+                let t = n * Vector3::from(*v);
+                *v = if t.magnitude2() > 0.0 { t.normalize().into() } else { *v };
+            }
+        }
+        if let Some(tangents) = &mut mesh.tangents {
+            for v in tangents.iter_mut() {
+                let t = m * Vector4::new(v[0], v[1], v[2], 0.0);
+                let t = if t.magnitude2() > 0.0 { t.truncate().normalize() } else { t.truncate() };
+                *v = [t.x, t.y, t.z, v[3]];
+            }
+        }
+        if m.determinant() < 0.0 {
+            match &mut mesh.indices {
+                Indices::U16(v) => v.chunks_exact_mut(3).for_each(|t| t.swap(1, 2)),
+                Indices::U32(v) => v.chunks_exact_mut(3).for_each(|t| t.swap(1, 2)),
+            }
+            if let Some(tangents) = &mut mesh.tangents {
+                for v in tangents.iter_mut() {
+                    v[3] = -v[3];
+                }
+            }
+        }
+        mesh
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GltfScene {
+    pub primitives: Vec<GltfPrimitive>,
+    pub materials: Vec<Material>,
+}
+
+// Loads a .gltf or .glb file. Buffers may live in the glb binary chunk, in data uris or in
+// external files next to the .gltf; images referenced by uri become TextureSource::File so
+// they are loaded like the examples' assets/*.png, while images stored in the file become
+// TextureSource::Embedded (see TextureSource::to_file).
+pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<GltfScene> {
+    let path = path.as_ref();
+    let gltf = gltf::Gltf::open(path).with_context(|| format!("failed to load {}", path.display()))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let doc = &gltf.document;
+
+    let mut buffers: Vec<Vec<u8>> = vec![];
+    for buffer in doc.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf.blob.clone().context("glb file has no binary chunk")?,
+            gltf::buffer::Source::Uri(uri) => read_uri(uri, dir)?,
+        };
+        if data.len() < buffer.length() {
+            bail!("buffer {} is shorter than its declared length", buffer.index());
+        }
+        buffers.push(data);
+    }
+
+    let mut images: Vec<TextureSource> = vec![];
+    for image in doc.images() {
+        let name = image.name().map(String::from).unwrap_or_else(|| format!("image{}", image.index()));
+        images.push(match image.source() {
+            gltf::image::Source::View { view, mime_type } => {
+                let start = view.offset();
+                let data = buffers.get(view.buffer().index())
+                    .and_then(|b| b.get(start..start + view.length()))
+                    .with_context(|| format!("image {} lies outside its buffer", image.index()))?
+                    .to_vec();
+                TextureSource::Embedded { name, mime_type: mime_type.to_string(), data }
+            }
+            gltf::image::Source::Uri { uri, mime_type } => {
+                if uri.starts_with("data:") {
+                    let mime_type = mime_type.map(String::from)
+                        .or_else(|| uri[5..].split(';').next().map(String::from))
+                        .unwrap_or_default();
+                    TextureSource::Embedded { name, mime_type, data: read_uri(uri, dir)? }
+                } else {
+                    TextureSource::File(dir.join(percent_decode(uri)))
+                }
+            }
+        });
+    }
+
+    // the image sampled by each texture
+    let textures: Vec<TextureSource> = doc.textures().map(|t| images[t.source().index()].clone()).collect();
+    let materials = doc.materials().map(|m| import_material(&m, &textures)).collect();
+
+    let mut primitives: Vec<GltfPrimitive> = vec![];
+    let scene = doc.default_scene().or_else(|| doc.scenes().next());
+    match scene {
+        Some(scene) => {
+            for node in scene.nodes() {
+                import_node(&node, Matrix4::identity(), &buffers, &mut primitives)?;
+            }
+        }
+        // no scene: take every mesh as it is
+        None => {
+            for mesh in doc.meshes() {
+                import_mesh(&mesh, Matrix4::identity(), &buffers, &mut primitives)?;
+            }
+        }
+    }
+    Ok(GltfScene { primitives, materials })
+}
+
+fn import_node(node: &gltf::Node, parent: Matrix4<f32>, buffers: &[Vec<u8>], 
+    out: &mut Vec<GltfPrimitive>) -> Result<()> {
+    let transform = parent * Matrix4::from(node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        import_mesh(&mesh, transform, buffers, out)?;
+    }
+    for child in node.children() {
+        import_node(&child, transform, buffers, out)?;
+    }
+    Ok(())
+}
+
+fn import_mesh(mesh: &gltf::Mesh, transform: Matrix4<f32>, buffers: &[Vec<u8>], 
+    out: &mut Vec<GltfPrimitive>) -> Result<()> {
+    let name = mesh.name().map(String::from).unwrap_or_else(|| format!("mesh{}", mesh.index()));
+    for primitive in mesh.primitives() {
+        let reader = primitive.reader(|b| buffers.get(b.index()).map(|d| d.as_slice()));
+        let positions: Vec<[f32; 3]> = match reader.read_positions() {
+            Some(p) => p.collect(),
+            None => bail!("{}: primitive {} has no positions", name, primitive.index()),
+        };
+        let n = positions.len();
+        let sequential: Vec<u32> = (0..n as u32).collect();
+        let indices: Vec<u32> = reader.read_indices().map(|i| i.into_u32().collect()).unwrap_or(sequential);
+
+        let triangles: Vec<u32> = match primitive.mode() {
+            gltf::mesh::Mode::Triangles => indices,
+            gltf::mesh::Mode::TriangleStrip => (2..indices.len()).flat_map(|k| {
+                if k % 2 == 0 { [indices[k-2], indices[k-1], indices[k]] } 
+                else { [indices[k-1], indices[k-2], indices[k]] }
+            }).collect(),
+            gltf::mesh::Mode::TriangleFan => (2..indices.len())
+                .flat_map(|k| [indices[0], indices[k-1], indices[k]]).collect(),
+            // points and lines have no surface to texture
+            _ => continue,
+        };
+
+        let mut uv_sets: Vec<Vec<[f32; 2]>> = vec![];
+        while let Some(uvs) = reader.read_tex_coords(uv_sets.len() as u32) {
+            uv_sets.push(uvs.into_f32().collect());
+        }
+        let colors: Option<Vec<[f32; 3]>> = reader.read_colors(0)
+            .map(|c| c.into_rgb_f32().collect());
+
+        let mut indices2: Vec<u32> = vec![];
+        for t in triangles.chunks_exact(3) {
+            indices2.extend([t[0], t[1], t[1], t[2], t[2], t[0]]);
+        }
+        let mut m = Mesh {
+            positions,
+            normals: reader.read_normals().map(|v| v.collect()),
+            uvs: uv_sets.first().cloned(),
+            colors,
+            tangents: reader.read_tangents().map(|v| v.collect()),
+            indices: Indices::auto(triangles, n),
+            indices2: Indices::auto(indices2, n),
+        };
+        m.validate().with_context(|| format!("{}: invalid primitive {}", name, primitive.index()))?;
+        if m.normals.is_none() {
+            m.compute_normals();
+        }
+
+        out.push(GltfPrimitive {
+            name: name.clone(),
+            mesh: m,
+            uv_sets,
+            transform: transform.into(),
+            material_id: primitive.material().index(),
+        });
+    }
+    Ok(())
+}
+
+// Inverse of gltf_material: metallic-roughness parameters are mapped back onto Blinn-Phong,
+// preferring the exact values stored in the extras by write_glb.
+fn import_material(m: &gltf::Material, textures: &[TextureSource]) -> Material {
+    let pbr = m.pbr_metallic_roughness();
+    let image = |t: gltf::Texture| textures.get(t.index()).cloned();
+    let extras: Value = m.extras().as_ref()
+        .and_then(|e| serde_json::from_str(e.get()).ok())
+        .unwrap_or(Value::Null);
+
+    let c = pbr.base_color_factor();
+    let roughness = pbr.roughness_factor().max(1.0e-3);
+    let shininess = extras["shininess"].as_f64().map(|s| s as f32)
+        .unwrap_or(2.0 / (roughness * roughness) - 2.0);
+    let specular = match m.specular() {
+        Some(s) => s.specular_color_factor().map(|c| c * s.specular_factor()),
+        None => [0.04; 3],  // the glTF default reflectance of dielectrics
+    };
+    let d = Material::default();
+    let ambient = match &extras["ambient"] {
+        Value::Array(a) if a.len() == 3 => [0, 1, 2].map(|k| a[k].as_f64().unwrap_or(0.0) as f32),
+        _ => d.ambient,
+    };
+    let height_texture = extras["heightTexture"]["index"].as_u64()
+        .and_then(|i| textures.get(i as usize).cloned());
+
+    Material {
+        name: m.name().unwrap_or_default().to_string(),
+        ambient,
+        diffuse: [c[0], c[1], c[2]],
+        specular,
+        shininess,
+        diffuse_texture: pbr.base_color_texture().and_then(|t| image(t.texture())),
+        normal_texture: m.normal_texture().and_then(|t| image(t.texture())),
+        height_texture,
+        specular_texture: None,
+    }
+}
+
+fn read_uri(uri: &str, dir: &Path) -> Result<Vec<u8>> {
+    if let Some(rest) = uri.strip_prefix("data:") {
+        let (_, data) = rest.split_once(";base64,").context("only base64 data uris are supported")?;
+        return base64::engine::general_purpose::STANDARD.decode(data).context("invalid base64 data uri");
+    }
+    let path = dir.join(percent_decode(uri));
+    std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+// uris in glTF are percent-encoded, e.g. "brick%20normal.png"
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out: Vec<u8> = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i+1..i+3]).ok();
+            if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+// endregion: import
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex_data::create_sphere_data;
+
+    fn close<const N: usize>(a: [f32; N], b: [f32; N]) -> bool {
+        (0..N).all(|k| (a[k] - b[k]).abs() <= 1e-6)
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut mesh = create_sphere_data(1.0, 12, 8);
+        mesh.generate_tangents().unwrap();
+        // the image is stored as-is, so any bytes do
+        let png = TextureSource::Embedded {
+            name: "checker".to_string(),
+            mime_type: "image/png".to_string(),
+            data: b"\x89PNG\r\n\x1a\nnot really an image".to_vec(),
+        };
+        let material = Material { diffuse_texture: Some(png.clone()), ..Material::default() };
+
+        let path = std::env::temp_dir().join(format!("wgpu_textures_{}_round_trip.glb", std::process::id()));
+        save_glb(&path, &mesh, &material).unwrap();
+        let scene = load_gltf(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(scene.primitives.len(), 1);
+        let read = &scene.primitives[0].mesh;
+        assert_eq!(read.positions.len(), mesh.positions.len());
+        assert!(mesh.positions.iter().zip(&read.positions).all(|(a, b)| close(*a, *b)));
+        assert!(mesh.indices.iter().eq(read.indices.iter()));
+        let (tangents, read_tangents) = (mesh.tangents.as_ref().unwrap(), read.tangents.as_ref().unwrap());
+        assert!(tangents.iter().zip(read_tangents).all(|(a, b)| close(*a, *b)));
+
+        assert_eq!(scene.materials.len(), 1);
+        match (&scene.materials[0].diffuse_texture, &png) {
+            (Some(TextureSource::Embedded { mime_type, data, .. }), TextureSource::Embedded { data: expected, .. }) => {
+                assert_eq!(mime_type, "image/png");
+                assert_eq!(data, expected);
+            }
+            (t, _) => panic!("expected an embedded image, got {:?}", t),
+        }
+    }
+
+    // a buffer view that claims more bytes than its buffer holds
+    #[test]
+    fn image_outside_buffer() {
+        let material = Material {
+            diffuse_texture: Some(TextureSource::Embedded {
+                name: String::new(), mime_type: "image/png".to_string(), data: vec![0; 16],
+            }),
+            ..Material::default()
+        };
+        let mut bytes: Vec<u8> = vec![];
+        write_glb(&mut bytes, &create_sphere_data(1.0, 4, 4), &material).unwrap();
+        let json_len = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        let text = std::str::from_utf8(&bytes[20..20 + json_len]).unwrap();
+        let mut doc: Value = serde_json::from_str(text).unwrap();
+        let view = doc["images"][0]["bufferView"].as_u64().unwrap() as usize;
+        doc["bufferViews"][view]["byteLength"] = json!(1 << 20);
+        let mut json_bytes = serde_json::to_vec(&doc).unwrap();
+        while !json_bytes.len().is_multiple_of(4) {
+            json_bytes.push(b' ');
+        }
+        let bin = bytes[20 + json_len..].to_vec();
+        let mut out: Vec<u8> = b"glTF".to_vec();
+        out.extend(2u32.to_le_bytes());
+        out.extend(((12 + 8 + json_bytes.len() + bin.len()) as u32).to_le_bytes());
+        out.extend((json_bytes.len() as u32).to_le_bytes());
+        out.extend(b"JSON");
+        out.extend(json_bytes);
+        out.extend(bin);
+
+        let path = std::env::temp_dir().join(format!("wgpu_textures_{}_bad_view.glb", std::process::id()));
+        std::fs::write(&path, out).unwrap();
+        let result = load_gltf(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.unwrap_err().to_string().contains("outside its buffer"));
+    }
+}
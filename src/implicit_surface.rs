@@ -0,0 +1,253 @@
+#![allow(dead_code)]
+use cgmath::*;
+use std::collections::HashSet;
+use super::colormap;
+use super::expr::ImplicitExpr;
+use super::math_func as mf;
+use super::mesh::{IndexFormat, Indices};
+use super::surface_data::{tangent_frame, ISurfaceOutput};
+use anyhow::{bail, Result};
+
+type ImplicitFn = fn(f32, f32, f32, f32) -> f32;
+
+// Implicit surface f(x, y, z, t) = iso inside a bounding box, polygonized with surface nets:
+// a grid of cells is sampled, every cell the surface passes through gets one vertex at the
+// mean of its edge crossings, and every grid edge crossing the surface becomes a quad joining
+// the four cells around it. This is the mass-point variant of dual contouring; it gives
+// shared vertices and well shaped triangles without the marching cubes case tables.
+pub struct IImplicitSurface {
+    pub surface_type: u32,          // 0: gyroid, 1: metaballs
+    pub expression: Option<String>, // expr::ImplicitExpr source, used instead of surface_type
+    pub iso: f32,
+    // bounding box overrides; None uses the surface's default
+    pub min: Option<[f32; 3]>,
+    pub max: Option<[f32; 3]>,
+    pub resolution: [u16; 3],       // cells along x, y and z
+    pub scale: f32,
+    pub colormap_name: String,
+    pub wireframe_color: String,
+    pub colormap_direction: u32, // 0: x-direction, 1: y-direction, 2: z-direction
+    pub t: f32,  // animation time parameter
+    pub uv_lens: [f32; 2],
+    pub index_format: IndexFormat,
+}
+
+impl Default for IImplicitSurface {
+    fn default() -> Self {
+        Self {
+            surface_type: 0,
+            expression: None,
+            iso: 0.0,
+            min: None,
+            max: None,
+            resolution: [48, 48, 48],
+            scale: 1.0,
+            colormap_name: "jet".to_string(),
+            wireframe_color: "white".to_string(),
+            colormap_direction: 1,
+            t: 0.0,
+            uv_lens: [1.0, 1.0],
+            index_format: IndexFormat::Auto,
+        }
+    }
+}
+
+impl IImplicitSurface {
+    // function and default bounding box of the selected surface
+    fn surface(&self) -> (ImplicitFn, [f32; 3], [f32; 3]) {
+        let pi2 = 2.0 * std::f32::consts::PI;
+        if self.surface_type == 1 {
+            (mf::metaballs, [-2.0; 3], [2.0; 3])
+        } else {
+            (mf::gyroid, [-pi2; 3], [pi2; 3])
+        }
+    }
+
+    // The bounding box polygonize() samples.
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let (min, max) = match self.expression {
+            Some(_) => ([-1.0; 3], [1.0; 3]),
+            None => {
+                let (_, min, max) = self.surface();
+                (min, max)
+            }
+        };
+        (self.min.unwrap_or(min), self.max.unwrap_or(max))
+    }
+
+    // Polygonizes the expression, or else the built-in surface_type.
+    pub fn polygonize(&mut self) -> Result<ISurfaceOutput> {
+        match self.expression.clone() {
+            Some(source) => {
+                let e = ImplicitExpr::parse(&source)?;
+                self.implicit_surface_data(&|x, y, z, t| e.eval(x, y, z, t))
+            }
+            None => {
+                let (f, _, _) = self.surface();
+                self.implicit_surface_data(&f)
+            }
+        }
+    }
+
+    // Polygonizes any function f(x, y, z, t). Normals follow the gradient of f, so they point
+    // toward f > iso. The uvs are the planar projection along the dominant normal axis, which
+    // keeps the texture undistorted on each side; for seamless results sample the texture
+    // triplanar in the shader from the positions and normals.
+    pub fn implicit_surface_data(&mut self, f:&dyn Fn(f32, f32, f32, f32) -> f32) -> Result<ISurfaceOutput> {
+        let (bmin, bmax) = self.bounds();
+        let [nx, ny, nz] = self.resolution.map(|n| n.max(1) as usize);
+        if (0..3).any(|k| bmax[k] <= bmin[k]) {
+            bail!("empty bounding box {:?} - {:?}", bmin, bmax);
+        }
+        let cell = [0, 1, 2].map(|k| (bmax[k] - bmin[k]) / [nx, ny, nz][k] as f32);
+        let corner = |i:usize, j:usize, k:usize| {
+            [bmin[0] + cell[0] * i as f32, bmin[1] + cell[1] * j as f32, bmin[2] + cell[2] * k as f32]
+        };
+
+        // field values at the grid corners, relative to iso
+        let ci = |i:usize, j:usize, k:usize| i + (nx + 1) * (j + (ny + 1) * k);
+        let mut values = vec![0.0f32; (nx + 1) * (ny + 1) * (nz + 1)];
+        for k in 0..=nz {
+            for j in 0..=ny {
+                for i in 0..=nx {
+                    let p = corner(i, j, k);
+                    values[ci(i, j, k)] = f(p[0], p[1], p[2], self.t) - self.iso;
+                }
+            }
+        }
+        let inside = |v:f32| v < 0.0;
+        // edges with a non-finite end are treated as not crossing, leaving a hole
+        let crosses = |a:f32, b:f32| a.is_finite() && b.is_finite() && inside(a) != inside(b);
+
+        // one vertex per cell crossed by the surface
+        let cell_index = |i:usize, j:usize, k:usize| i + nx * (j + ny * k);
+        let mut cell_vertex = vec![u32::MAX; nx * ny * nz];
+        let mut points: Vec<Vector3<f32>> = vec![];
+        const EDGES: [([usize; 3], [usize; 3]); 12] = [
+            ([0, 0, 0], [1, 0, 0]), ([0, 1, 0], [1, 1, 0]), ([0, 0, 1], [1, 0, 1]), ([0, 1, 1], [1, 1, 1]),
+            ([0, 0, 0], [0, 1, 0]), ([1, 0, 0], [1, 1, 0]), ([0, 0, 1], [0, 1, 1]), ([1, 0, 1], [1, 1, 1]),
+            ([0, 0, 0], [0, 0, 1]), ([1, 0, 0], [1, 0, 1]), ([0, 1, 0], [0, 1, 1]), ([1, 1, 0], [1, 1, 1]),
+        ];
+        for k in 0..nz {
+            for j in 0..ny {
+                for i in 0..nx {
+                    let mut sum = Vector3::new(0.0, 0.0, 0.0);
+                    let mut count = 0;
+                    for (a, b) in EDGES {
+                        let va = values[ci(i + a[0], j + a[1], k + a[2])];
+                        let vb = values[ci(i + b[0], j + b[1], k + b[2])];
+                        if crosses(va, vb) {
+                            let s = va / (va - vb);
+                            let pa = Vector3::from(corner(i + a[0], j + a[1], k + a[2]));
+                            let pb = Vector3::from(corner(i + b[0], j + b[1], k + b[2]));
+                            sum += pa + (pb - pa) * s;
+                            count += 1;
+                        }
+                    }
+                    if count > 0 {
+                        cell_vertex[cell_index(i, j, k)] = points.len() as u32;
+                        points.push(sum / count as f32);
+                    }
+                }
+            }
+        }
+
+        // a quad for every interior grid edge crossing the surface; the four cells are listed
+        // counterclockwise around the edge direction and reversed when f decreases along it,
+        // so the quads face toward f > iso like the normals
+        let mut indices: Vec<u32> = vec![];
+        let mut lines: HashSet<(u32, u32)> = HashSet::new();
+        let mut indices2: Vec<u32> = vec![];
+        for k in 0..=nz {
+            for j in 0..=ny {
+                for i in 0..=nx {
+                    let v0 = values[ci(i, j, k)];
+                    for axis in 0..3 {
+                        let (cells, v1) = match axis {
+                            0 if i < nx && j > 0 && k > 0 && j < ny && k < nz => (
+                                [(i, j-1, k-1), (i, j, k-1), (i, j, k), (i, j-1, k)], values[ci(i+1, j, k)]),
+                            1 if j < ny && i > 0 && k > 0 && i < nx && k < nz => (
+                                [(i-1, j, k-1), (i-1, j, k), (i, j, k), (i, j, k-1)], values[ci(i, j+1, k)]),
+                            2 if k < nz && i > 0 && j > 0 && i < nx && j < ny => (
+                                [(i-1, j-1, k), (i, j-1, k), (i, j, k), (i-1, j, k)], values[ci(i, j, k+1)]),
+                            _ => continue,
+                        };
+                        if !crosses(v0, v1) {
+                            continue;
+                        }
+                        let mut q = cells.map(|(a, b, c)| cell_vertex[cell_index(a, b, c)]);
+                        if !inside(v0) {
+                            q.reverse();
+                        }
+                        // split along the shorter diagonal
+                        let d02 = (points[q[0] as usize] - points[q[2] as usize]).magnitude2();
+                        let d13 = (points[q[1] as usize] - points[q[3] as usize]).magnitude2();
+                        if d02 <= d13 {
+                            indices.extend([q[0], q[1], q[2], q[2], q[3], q[0]]);
+                        } else {
+                            indices.extend([q[1], q[2], q[3], q[3], q[0], q[1]]);
+                        }
+                        for e in 0..4 {
+                            let (a, b) = (q[e], q[(e + 1) % 4]);
+                            if lines.insert((a.min(b), a.max(b))) {
+                                indices2.extend([a, b]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // map the box to [-scale, scale] along its longest side
+        let center = Vector3::from([0, 1, 2].map(|k| 0.5 * (bmin[k] + bmax[k])));
+        let extent = (0..3).map(|k| bmax[k] - bmin[k]).fold(0.0f32, f32::max);
+        let to_output = |p:Vector3<f32>| (p - center) * (2.0 * self.scale / extent);
+
+        let mut out = ISurfaceOutput::default();
+        let eps = 0.01 * cell.iter().cloned().fold(f32::MAX, f32::min);
+        let axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+        for p in &points {
+            let pos = to_output(*p);
+            out.positions.push(pos.into());
+
+            let g = |dx:Vector3<f32>| f(p.x + dx.x, p.y + dx.y, p.z + dx.z, self.t)
+                - f(p.x - dx.x, p.y - dx.y, p.z - dx.z, self.t);
+            let gradient = Vector3::new(g(axes[0] * eps), g(axes[1] * eps), g(axes[2] * eps));
+            let normal = if gradient.magnitude2() > 0.0 { gradient.normalize() } else { Vector3::unit_y() };
+            out.normals.push(normal.into());
+
+            // planar uvs on the plane of the dominant normal axis
+            let a = normal.map(|c| c.abs());
+            let (u_axis, v_axis) = if a.x >= a.y && a.x >= a.z {
+                (2, 1)
+            } else if a.y >= a.z {
+                (0, 2)
+            } else {
+                (0, 1)
+            };
+            let uv = [
+                self.uv_lens[0] * (0.5 + 0.5 * pos[u_axis] / self.scale),
+                self.uv_lens[1] * (0.5 - 0.5 * pos[v_axis] / self.scale),
+            ];
+            out.uvs.push(uv);
+            let (tangent, bitangent) = tangent_frame(normal, axes[u_axis], -axes[v_axis]);
+            out.tangents.push(tangent);
+            out.bitangents.push(bitangent);
+        }
+
+        let cdata = colormap::colormap_data(&self.colormap_name);
+        let cdata2 = colormap::colormap_data(&self.wireframe_color);
+        let d = self.colormap_direction as usize;
+        let values: Vec<f32> = out.positions.iter().map(|p| p[d]).collect();
+        let (min_val, max_val) = colormap::scalar_range(&values);
+        for &c in &values {
+            out.colors.push(colormap::color_lerp(cdata, min_val, max_val, c));
+            out.colors2.push(colormap::color_lerp(cdata2, min_val, max_val, c));
+        }
+
+        let n = out.positions.len();
+        out.indices = Indices::new(indices, n, self.index_format)?;
+        out.indices2 = Indices::new(indices2, n, self.index_format)?;
+        Ok(out)
+    }
+}
@@ -1,4 +1,18 @@
+pub mod mesh;
 pub mod vertex_data;
 pub mod colormap;
+pub mod curvature;
+pub mod topology;
+pub mod lod;
+pub mod dual;
+pub mod parallel;
 pub mod math_func;
-pub mod surface_data;
\ No newline at end of file
+pub mod expr;
+pub mod surface_registry;
+pub mod adaptive;
+pub mod surface_data;
+pub mod implicit_surface;
+pub mod sweep;
+pub mod obj;
+pub mod ply;
+pub mod gltf_io;
\ No newline at end of file
@@ -0,0 +1,486 @@
+#![allow(dead_code)]
+use cgmath::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use super::mesh::{Indices, Mesh};
+use super::topology::weld_by_position;
+
+// One level of detail with its geometric error: how far its surface may be from the finest
+// level, in the units of the positions.
+#[derive(Debug, Clone)]
+pub struct Lod<T> {
+    pub mesh: T,
+    pub error: f32,
+}
+
+// Levels of detail from the finest (level 0, error 0) to the coarsest.
+#[derive(Debug, Clone)]
+pub struct LodChain<T> {
+    pub levels: Vec<Lod<T>>,
+}
+
+impl<T> LodChain<T> {
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    // Error of a level in pixels when seen from distance with a perspective projection of
+    // vertical field of view fovy (in degrees) onto a viewport viewport_height pixels high;
+    // None for a level the chain does not have, like get.
+    pub fn screen_error(&self, level:usize, distance:f32, fovy:f32, viewport_height:f32) -> Option<f32> {
+        let error = self.levels.get(level)?.error;
+        let d = distance.max(f32::EPSILON);
+        Some(error * viewport_height / (2.0 * d * (0.5 * fovy.to_radians()).tan()))
+    }
+
+    // The coarsest level whose screen error stays within max_pixels; level 0 when none does.
+    pub fn select(&self, distance:f32, fovy:f32, viewport_height:f32, max_pixels:f32) -> usize {
+        (0..self.levels.len()).rev()
+            .find(|&i| self.screen_error(i, distance, fovy, viewport_height).is_some_and(|e| e <= max_pixels))
+            .unwrap_or(0)
+    }
+
+    pub fn get(&self, level:usize) -> Option<&T> {
+        self.levels.get(level).map(|l| &l.mesh)
+    }
+}
+
+// region: grid decimation
+// Every step-th of the n + 1 grid lines, and the last one.
+fn grid_lines(n:usize, step:usize) -> Vec<usize> {
+    let mut lines: Vec<usize> = (0..n).step_by(step.max(1)).collect();
+    lines.push(n);
+    lines
+}
+
+// A grid of (nu + 1) x (nv + 1) vertices, stored with v varying fastest as the surfaces of
+// surface_data do, decimated to every step-th row and column (and the last ones). Returns
+// the kept vertices, the triangle and line indices into them, wound and laid out like the
+// full grid, and the largest distance of a used vertex of the full grid from the decimated
+// triangles over the same cell. Cells with a vertex outside used are left out.
+pub(crate) fn decimate_grid(positions:&[[f32; 3]], used:&[bool], nu:usize, nv:usize, step:usize)
+    -> (Vec<usize>, Vec<u32>, Vec<u32>, f32) {
+    let (us, vs) = (grid_lines(nu, step), grid_lines(nv, step));
+    let id = |i:usize, j:usize| i * (nv + 1) + j;
+    let mut kept: Vec<usize> = vec![];
+    for &i in &us {
+        for &j in &vs {
+            kept.push(id(i, j));
+        }
+    }
+
+    let mut indices: Vec<u32> = vec![];
+    let mut indices2: Vec<u32> = vec![];
+    let mut error = 0.0f32;
+    let per_row = vs.len() as u32;
+    let p = |k:usize| Vector3::from(positions[k]);
+    for a in 0..us.len() - 1 {
+        for b in 0..vs.len() - 1 {
+            let (i0, i1, j0, j1) = (us[a], us[a + 1], vs[b], vs[b + 1]);
+            let corners = [id(i0, j0), id(i0, j1), id(i1, j1), id(i1, j0)];
+            if corners.iter().any(|&k| !used[k]) {
+                continue;
+            }
+            let idx0 = b as u32 + a as u32 * per_row;
+            let (idx1, idx2, idx3) = (idx0 + 1, idx0 + 1 + per_row, idx0 + per_row);
+            indices.extend([idx0, idx1, idx2, idx2, idx3, idx0]);
+            indices2.extend([idx0, idx1, idx0, idx3]);
+            if a == us.len() - 2 || b == vs.len() - 2 {
+                indices2.extend([idx1, idx2, idx2, idx3]);
+            }
+
+            // the cell is split along (i0, j0) - (i1, j1)
+            let [p00, p01, p11, p10] = corners.map(p);
+            for i in i0..=i1 {
+                for j in j0..=j1 {
+                    if !used[id(i, j)] {
+                        continue;
+                    }
+                    let s = (i - i0) as f32 / (i1 - i0) as f32;
+                    let t = (j - j0) as f32 / (j1 - j0) as f32;
+                    let q = if t >= s {
+                        p00 + (p11 - p01) * s + (p01 - p00) * t
+                    } else {
+                        p00 + (p10 - p00) * s + (p11 - p10) * t
+                    };
+                    let d = (p(id(i, j)) - q).magnitude();
+                    if d.is_finite() {
+                        error = error.max(d);
+                    }
+                }
+            }
+        }
+    }
+    (kept, indices, indices2, error)
+}
+// endregion: grid decimation
+
+// region: mesh simplification
+// Symmetric 4x4 matrix of the squared distance to a set of planes (Garland and Heckbert).
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane(n:Vector3<f64>, d:f64) -> Self {
+        let (a, b, c) = (n.x, n.y, n.z);
+        Quadric([a*a, a*b, a*c, a*d, b*b, b*c, b*d, c*c, c*d, d*d])
+    }
+
+    fn add(self, o:Quadric) -> Self {
+        let mut q = self.0;
+        for (x, y) in q.iter_mut().zip(o.0) {
+            *x += y;
+        }
+        Quadric(q)
+    }
+
+    fn error(&self, p:Vector3<f64>) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x, p.y, p.z);
+        (q[0]*x*x + 2.0*q[1]*x*y + 2.0*q[2]*x*z + 2.0*q[3]*x + q[4]*y*y + 2.0*q[5]*y*z + 2.0*q[6]*y
+            + q[7]*z*z + 2.0*q[8]*z + q[9]).max(0.0)
+    }
+
+    // the point of least error, if the planes pin one down
+    fn minimum(&self) -> Option<Vector3<f64>> {
+        let q = &self.0;
+        let m = Matrix3::new(q[0], q[1], q[2], q[1], q[4], q[5], q[2], q[5], q[7]);
+        if m.determinant().abs() < 1e-12 {
+            return None;
+        }
+        m.invert().map(|inv| inv * Vector3::new(-q[3], -q[6], -q[8]))
+    }
+}
+
+struct Simplifier {
+    positions: Vec<Vector3<f64>>,
+    quadrics: Vec<Quadric>,
+    locked: Vec<bool>,
+    alive: Vec<bool>,          // vertices
+    version: Vec<u32>,         // bumped when a vertex changes, to skip stale heap entries
+    faces: Vec<[u32; 3]>,
+    face_alive: Vec<bool>,
+    vertex_faces: Vec<Vec<usize>>,
+    face_count: usize,
+    heap: BinaryHeap<(Reverse<u64>, u32, u32, u32, u32)>,  // cost, from, to, versions
+}
+
+impl Simplifier {
+    fn new(mesh:&Mesh) -> Self {
+        let n = mesh.positions.len();
+        let positions: Vec<Vector3<f64>> = mesh.positions.iter()
+            .map(|p| Vector3::new(p[0] as f64, p[1] as f64, p[2] as f64)).collect();
+        let faces: Vec<[u32; 3]> = mesh.indices.to_u32().chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]]).filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0]).collect();
+
+        // Vertices on an open edge or sharing their position with another vertex, as along uv
+        // seams, keep their place so that the mesh cannot tear open.
+        let mut locked = vec![false; n];
+        let weld = weld_by_position(&mesh.positions, 0.0);
+        for (i, &w) in weld.iter().enumerate() {
+            if w as usize != i {
+                locked[i] = true;
+                locked[w as usize] = true;
+            }
+        }
+        let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+        for t in &faces {
+            for k in 0..3 {
+                let (a, b) = (t[k], t[(k + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            if count != 2 {
+                locked[a as usize] = true;
+                locked[b as usize] = true;
+            }
+        }
+
+        // Unweighted planes, so that the error of a point is its summed squared distance to
+        // the planes and its root a length, which screen_error relies on.
+        let mut quadrics = vec![Quadric::default(); n];
+        let mut vertex_faces: Vec<Vec<usize>> = vec![vec![]; n];
+        for (f, t) in faces.iter().enumerate() {
+            let [a, b, c] = t.map(|i| positions[i as usize]);
+            let normal = (b - a).cross(c - a);
+            if normal.magnitude2() > 0.0 {
+                let normal = normal.normalize();
+                let q = Quadric::plane(normal, -normal.dot(a));
+                for &i in t {
+                    quadrics[i as usize] = quadrics[i as usize].add(q);
+                }
+            }
+            for &i in t {
+                vertex_faces[i as usize].push(f);
+            }
+        }
+
+        let face_count = faces.len();
+        let mut s = Simplifier {
+            positions,
+            quadrics,
+            locked,
+            alive: vec![true; n],
+            version: vec![0; n],
+            face_alive: vec![true; faces.len()],
+            faces,
+            vertex_faces,
+            face_count,
+            heap: BinaryHeap::new(),
+        };
+        for &(a, b) in edges.keys() {
+            s.push(a, b);
+        }
+        s
+    }
+
+    // Where the edge a - b collapses to, keeping one end: into the locked end, or to the point
+    // of least error.
+    fn plan(&self, a:u32, b:u32) -> Option<(f64, u32, u32, Vector3<f64>)> {
+        let (la, lb) = (self.locked[a as usize], self.locked[b as usize]);
+        let q = self.quadrics[a as usize].add(self.quadrics[b as usize]);
+        let (pa, pb) = (self.positions[a as usize], self.positions[b as usize]);
+        let (from, to, p) = match (la, lb) {
+            (true, true) => return None,
+            (true, false) => (b, a, pa),
+            (false, true) => (a, b, pb),
+            (false, false) => {
+                let mut candidates = vec![pa, pb, (pa + pb) * 0.5];
+                candidates.extend(q.minimum());
+                let p = candidates.into_iter().min_by(|x, y| q.error(*x).total_cmp(&q.error(*y)))?;
+                // keep the attributes of the nearer end
+                if (p - pa).magnitude2() <= (p - pb).magnitude2() { (b, a, p) } else { (a, b, p) }
+            }
+        };
+        Some((q.error(p), from, to, p))
+    }
+
+    fn push(&mut self, a:u32, b:u32) {
+        if let Some((cost, from, to, _)) = self.plan(a, b) {
+            let key = Reverse(cost.to_bits());
+            self.heap.push((key, from, to, self.version[from as usize], self.version[to as usize]));
+        }
+    }
+
+    fn neighbors(&self, v:u32) -> HashSet<u32> {
+        self.vertex_faces[v as usize].iter().filter(|&&f| self.face_alive[f])
+            .flat_map(|&f| self.faces[f]).filter(|&u| u != v).collect()
+    }
+
+    // Rejects collapses that would make the mesh non-manifold or flip or squash a triangle.
+    fn valid(&self, from:u32, to:u32, p:Vector3<f64>) -> bool {
+        let shared = self.vertex_faces[from as usize].iter()
+            .filter(|&&f| self.face_alive[f] && self.faces[f].contains(&to)).count();
+        let common = self.neighbors(from).intersection(&self.neighbors(to)).count();
+        if common != shared {
+            return false;
+        }
+        for &f in self.vertex_faces[from as usize].iter().filter(|&&f| self.face_alive[f]) {
+            let t = self.faces[f];
+            if t.contains(&to) {
+                continue;
+            }
+            let before = t.map(|i| self.positions[i as usize]);
+            let after = t.map(|i| if i == from { p } else { self.positions[i as usize] });
+            let n0 = (before[1] - before[0]).cross(before[2] - before[0]);
+            let n1 = (after[1] - after[0]).cross(after[2] - after[0]);
+            if n1.magnitude2() < 1e-24 || n0.dot(n1) <= 0.2 * n0.magnitude() * n1.magnitude() {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Collapses edges cheapest first until at most target faces are left or the next
+    // collapse would move the surface by more than max_error. Returns the largest error of
+    // the collapses made.
+    fn run(&mut self, target:usize, max_error:f64, mut error:f64) -> f64 {
+        while self.face_count > target {
+            let Some((Reverse(bits), from, to, vf, vt)) = self.heap.pop() else { break };
+            let (f, t) = (from as usize, to as usize);
+            if !self.alive[f] || !self.alive[t] || self.version[f] != vf || self.version[t] != vt {
+                continue;
+            }
+            let cost = f64::from_bits(bits);
+            if cost.sqrt() > max_error {
+                // the heap is sorted by cost, so nothing cheaper is left
+                self.heap.push((Reverse(bits), from, to, vf, vt));
+                break;
+            }
+            let Some((_, from2, to2, p)) = self.plan(from, to) else { continue };
+            if (from2, to2) != (from, to) || !self.valid(from, to, p) {
+                continue;
+            }
+
+            // move the faces of from over to to
+            for face in std::mem::take(&mut self.vertex_faces[f]) {
+                if !self.face_alive[face] {
+                    continue;
+                }
+                if self.faces[face].contains(&to) {
+                    self.face_alive[face] = false;
+                    self.face_count -= 1;
+                } else {
+                    for i in self.faces[face].iter_mut() {
+                        if *i == from {
+                            *i = to;
+                        }
+                    }
+                    self.vertex_faces[t].push(face);
+                }
+            }
+            self.vertex_faces[t].retain(|&face| self.face_alive[face]);
+            self.alive[f] = false;
+            self.positions[t] = p;
+            self.quadrics[t] = self.quadrics[t].add(self.quadrics[f]);
+            self.version[t] += 1;
+            error = error.max(cost.sqrt());
+            for n in self.neighbors(to) {
+                self.version[n as usize] += 1;
+            }
+            for n in self.neighbors(to) {
+                for m in self.neighbors(n) {
+                    self.push(n, m);
+                }
+            }
+        }
+        error
+    }
+
+    // The current mesh with the attributes of the original vertices that are left.
+    fn snapshot(&self, mesh:&Mesh) -> Mesh {
+        let mut index = vec![u32::MAX; self.positions.len()];
+        let mut kept: Vec<usize> = vec![];
+        let mut indices: Vec<u32> = vec![];
+        for (t, _) in self.faces.iter().zip(&self.face_alive).filter(|(_, &alive)| alive) {
+            for &i in t {
+                if index[i as usize] == u32::MAX {
+                    index[i as usize] = kept.len() as u32;
+                    kept.push(i as usize);
+                }
+                indices.push(index[i as usize]);
+            }
+        }
+        let mut lines: HashSet<(u32, u32)> = HashSet::new();
+        let mut indices2: Vec<u32> = vec![];
+        for t in indices.chunks(3) {
+            for k in 0..3 {
+                let (a, b) = (t[k], t[(k + 1) % 3]);
+                if lines.insert((a.min(b), a.max(b))) {
+                    indices2.extend([a, b]);
+                }
+            }
+        }
+        fn pick<T:Copy>(v:&Option<Vec<T>>, kept:&[usize]) -> Option<Vec<T>> {
+            v.as_ref().map(|v| kept.iter().map(|&i| v[i]).collect())
+        }
+        let n = kept.len();
+        Mesh {
+            positions: kept.iter().map(|&i| self.positions[i].map(|c| c as f32).into()).collect(),
+            normals: pick(&mesh.normals, &kept),
+            uvs: pick(&mesh.uvs, &kept),
+            colors: pick(&mesh.colors, &kept),
+            tangents: pick(&mesh.tangents, &kept),
+            indices: Indices::auto(indices, n),
+            indices2: Indices::auto(indices2, n),
+        }
+    }
+}
+
+// Chain of simplified meshes, each with about half the triangles of the one before, by
+// quadric edge collapse. Simplification stops once a collapse would move the surface by more
+// than max_error, so the chain may have fewer than levels levels. Vertices on open edges and
+// on attribute seams (vertices sharing a position) stay in place. The error of a level is
+// the quadric error bound of its collapses: the root of the summed squared distances of a
+// vertex to the planes of the original triangles around it, which bounds its distance to
+// each of them.
+pub fn simplify_chain(mesh:&Mesh, levels:usize, max_error:f32) -> LodChain<Mesh> {
+    let mut chain = LodChain { levels: vec![Lod { mesh: mesh.clone(), error: 0.0 }] };
+    let mut s = Simplifier::new(mesh);
+    let mut error = 0.0;
+    while chain.len() < levels.max(1) {
+        let before = s.face_count;
+        error = s.run(before / 2, max_error as f64, error);
+        if s.face_count == before {
+            break;
+        }
+        chain.levels.push(Lod { mesh: s.snapshot(mesh), error: error as f32 });
+    }
+    chain
+}
+// endregion: mesh simplification
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex_data::create_sphere_data;
+
+    // a wavy height field over a grid of 32 x 32 cells, stored v fastest
+    fn wavy_grid() -> Vec<[f32; 3]> {
+        let mut positions = vec![];
+        for i in 0..=32 {
+            for j in 0..=32 {
+                let (x, z) = (i as f32 / 32.0, j as f32 / 32.0);
+                positions.push([x, 0.1 * (6.0 * x).sin() * (4.0 * z).cos(), z]);
+            }
+        }
+        positions
+    }
+
+    #[test]
+    fn grid_levels() {
+        let positions = wavy_grid();
+        let used = vec![true; positions.len()];
+        let mut last_error = 0.0;
+        for (k, step) in [2, 4, 8, 16].into_iter().enumerate() {
+            let (kept, indices, indices2, error) = decimate_grid(&positions, &used, 32, 32, step);
+            // every level halves the rows and the columns of the one before
+            let cells = 32 / step;
+            assert_eq!(kept.len(), (cells + 1) * (cells + 1), "level {}", k + 1);
+            assert_eq!(indices.len(), 6 * cells * cells, "level {}", k + 1);
+            assert!(indices.iter().chain(&indices2).all(|&i| (i as usize) < kept.len()));
+            assert!(error > last_error, "level {}: error {} after {}", k + 1, error, last_error);
+            last_error = error;
+        }
+    }
+
+    #[test]
+    fn simplified_levels() {
+        let mesh = create_sphere_data(1.0, 48, 24);
+        let chain = simplify_chain(&mesh, 4, 1.0);
+        assert_eq!(chain.len(), 4);
+        for k in 1..chain.len() {
+            let (before, after) = (&chain.levels[k - 1], &chain.levels[k]);
+            let ratio = after.mesh.triangle_count() as f32 / before.mesh.triangle_count() as f32;
+            assert!((0.4..=0.6).contains(&ratio), "level {}: {} of the triangles", k, ratio);
+            assert!(after.error > before.error, "level {}: error {} after {}", k, after.error, before.error);
+        }
+    }
+
+    // simplification stops at max_error
+    #[test]
+    fn simplify_within_max_error() {
+        let mesh = create_sphere_data(1.0, 48, 24);
+        let chain = simplify_chain(&mesh, 10, 0.01);
+        assert!(chain.len() < 10);
+        assert!(chain.levels.iter().all(|l| l.error <= 0.01));
+    }
+
+    #[test]
+    fn screen_error() {
+        let chain = LodChain { levels: vec![Lod { mesh: (), error: 0.0 }, Lod { mesh: (), error: 0.01 }] };
+        // at 90 degrees the viewport spans 2 * distance
+        let e = chain.screen_error(1, 2.0, 90.0, 1000.0).unwrap();
+        assert!((e - 2.5).abs() < 1e-4, "{}", e);
+        assert_eq!(chain.screen_error(2, 2.0, 90.0, 1000.0), None);
+        assert_eq!(chain.get(2), None);
+        assert_eq!(chain.select(2.0, 90.0, 1000.0, 1.0), 0);
+        assert_eq!(chain.select(20.0, 90.0, 1000.0, 1.0), 1);
+        assert_eq!(LodChain::<()> { levels: vec![] }.select(2.0, 90.0, 1000.0, 1.0), 0);
+    }
+}
@@ -1,218 +1,236 @@
 #![allow(dead_code)]
+// the range checks stay written out: the dual numbers only compare against f32 from the left
+#![allow(clippy::manual_range_contains)]
 use std::f32::consts::PI;
 
-pub fn torus(u:f32, v:f32) -> [f32; 3] {
-    let x = (1.0 + 0.3 * v.cos()) * u.cos();
-    let y = 0.3 * v.sin();
-    let z = (1.0 + 0.3 * v.cos()) * u.sin();
-    [x, y, z]
+// The parametric surfaces are compiled twice: for f32, and in the dual module for dual
+// numbers, which evaluates a surface together with its exact partial derivatives.
+macro_rules! parametric_surfaces {
+    ($(pub fn $name:ident($u:ident:f32, $v:ident:f32) -> [f32; 3] $body:block)*) => {
+        $(pub fn $name($u:f32, $v:f32) -> [f32; 3] $body)*
+
+        pub mod dual {
+            use super::*;
+            use crate::dual::Dual;
+            $(pub fn $name($u:Dual, $v:Dual) -> [Dual; 3] $body)*
+        }
+    };
 }
 
-pub fn sphere(u:f32, v:f32) -> [f32; 3] {
-    let x = v.sin() * u.cos();
-    let y = v.cos();
-    let z = -v.sin() * u.sin();
-    [x, y, z]
-}
+parametric_surfaces! {
+    pub fn torus(u:f32, v:f32) -> [f32; 3] {
+        let x = (1.0 + 0.3 * v.cos()) * u.cos();
+        let y = 0.3 * v.sin();
+        let z = (1.0 + 0.3 * v.cos()) * u.sin();
+        [x, y, z]
+    }
+
+    pub fn sphere(u:f32, v:f32) -> [f32; 3] {
+        let x = v.sin() * u.cos();
+        let y = v.cos();
+        let z = -v.sin() * u.sin();
+        [x, y, z]
+    }
 
-pub fn breather(u:f32, v:f32) -> [f32; 3] {
-    const A:f32 = 0.4; // where 0 < A < 1
+    pub fn breather(u:f32, v:f32) -> [f32; 3] {
+        const A:f32 = 0.4; // where 0 < A < 1
 
-    let de = A*((1.0-A*A)* ((A*u).cosh()).powf(2.0)+A*A*((((1.0-A*A).sqrt()*v).sin()).powf(2.0)));
+        let de = A*((1.0-A*A)* ((A*u).cosh()).powf(2.0)+A*A*((((1.0-A*A).sqrt()*v).sin()).powf(2.0)));
 
-    let x = -u+(2.0*(1.0-A*A)*(A*u).cosh()*(A*u).sinh())/de;
+        let x = -u+(2.0*(1.0-A*A)*(A*u).cosh()*(A*u).sinh())/de;
     
-    let y = (2.0*(1.0-A*A).sqrt()*(A*u).cosh()*(-((1.0-A*A).sqrt()*v.cos()*((1.0-A*A).sqrt()*v).cos()) - 
-        v.sin()*((1.0-A*A).sqrt()*v).sin()))/de;    
+        let y = (2.0*(1.0-A*A).sqrt()*(A*u).cosh()*(-((1.0-A*A).sqrt()*v.cos()*((1.0-A*A).sqrt()*v).cos()) - 
+            v.sin()*((1.0-A*A).sqrt()*v).sin()))/de;    
     
-    let z = (2.0*(1.0-A*A).sqrt()*(A*u).cosh()*(-((1.0-A*A).sqrt()*v.sin()*((1.0-A*A).sqrt()*v).cos()) + 
-        v.cos()*((1.0-A*A).sqrt()*v).sin()))/de;
+        let z = (2.0*(1.0-A*A).sqrt()*(A*u).cosh()*(-((1.0-A*A).sqrt()*v.sin()*((1.0-A*A).sqrt()*v).cos()) + 
+            v.cos()*((1.0-A*A).sqrt()*v).sin()))/de;
 
-    [x, y, z]
-}
+        [x, y, z]
+    }
 
-pub fn sievert_enneper(u:f32, v:f32) -> [f32; 3] {
-    const A:f32 = 1.0;
+    pub fn sievert_enneper(u:f32, v:f32) -> [f32; 3] {
+        const A:f32 = 1.0;
     
-    let pu = -u/(1.0+A).sqrt() + (u.tan()*(1.0+A).sqrt()).atan();
-    let auv = 2.0/(1.0+A-A*v.sin()*v.sin()*u.cos()*u.cos());
-    let ruv = auv*v.sin()*((1.0+1.0/A)*(1.0+A*u.sin()*u.sin())).sqrt();
+        let pu = -u/(1.0+A).sqrt() + (u.tan()*(1.0+A).sqrt()).atan();
+        let auv = 2.0/(1.0+A-A*v.sin()*v.sin()*u.cos()*u.cos());
+        let ruv = auv*v.sin()*((1.0+1.0/A)*(1.0+A*u.sin()*u.sin())).sqrt();
 
-    let x = (((v/2.0).tan()).ln() + (1.0+A)*auv*v.cos()) /A.sqrt();
-    let y = ruv*pu.cos();
-    let z = ruv*pu.sin();
+        let x = (((v/2.0).tan()).ln() + (1.0+A)*auv*v.cos()) /A.sqrt();
+        let y = ruv*pu.cos();
+        let z = ruv*pu.sin();
 
-    [x, y, z]
-}
+        [x, y, z]
+    }
 
-pub fn seashell(u:f32, v:f32) -> [f32; 3] {
-    let x = 2.0*(-1.0+(u/(6.0*PI)).exp())*u.sin()*(((v/2.0).cos()).powf(2.0));
+    pub fn seashell(u:f32, v:f32) -> [f32; 3] {
+        let x = 2.0*(-1.0+(u/(6.0*PI)).exp())*u.sin()*(((v/2.0).cos()).powf(2.0));
 
-    let y = 1.0 - (u/(3.0*PI)).exp()-v.sin() + (u/(6.0*PI)).exp()*v.sin();
+        let y = 1.0 - (u/(3.0*PI)).exp()-v.sin() + (u/(6.0*PI)).exp()*v.sin();
 
-    let z = 2.0*(1.0-(u/(6.0*PI)).exp())*u.cos()*((v/2.0).cos()).powf(2.0);
+        let z = 2.0*(1.0-(u/(6.0*PI)).exp())*u.cos()*((v/2.0).cos()).powf(2.0);
 
-    [x, y, z]
-}
+        [x, y, z]
+    }
 
-pub fn wellenkugel(u:f32, v:f32) -> [f32; 3] {
-    let x = u*(u.cos()).cos()*v.sin();        
-    let y = u*(u.cos()).sin();
-    let z = u*(u.cos()).cos()*v.cos();    
-    [x, y, z]   
-}
+    pub fn wellenkugel(u:f32, v:f32) -> [f32; 3] {
+        let x = u*(u.cos()).cos()*v.sin();        
+        let y = u*(u.cos()).sin();
+        let z = u*(u.cos()).cos()*v.cos();    
+        [x, y, z]   
+    }
 
-pub fn figure8(u:f32, v:f32) -> [f32; 3] {
-    let a = 2.5f32;
-    let x = (a + (0.5 * u).cos() * v.sin() - (0.5 * u).sin() * (2.0 * v).sin()) * u.cos();        
-    let y = (a + (0.5 * u).cos() * v.sin() - (0.5 * u).sin() * (2.0 * v).sin()) * u.sin();
-    let z = (0.5 * u).sin() * v.sin() + (0.5 * u).cos() * (2.0 * v).sin();    
-    [x, y, z]   
-}
+    pub fn figure8(u:f32, v:f32) -> [f32; 3] {
+        let a = 2.5f32;
+        let x = (a + (0.5 * u).cos() * v.sin() - (0.5 * u).sin() * (2.0 * v).sin()) * u.cos();        
+        let y = (a + (0.5 * u).cos() * v.sin() - (0.5 * u).sin() * (2.0 * v).sin()) * u.sin();
+        let z = (0.5 * u).sin() * v.sin() + (0.5 * u).cos() * (2.0 * v).sin();    
+        [x, y, z]   
+    }
 
-pub fn klein_bottle3(u:f32, v:f32) -> [f32; 3] {
-    let a = 8f32;
-    let n = 3f32;
-    let m = 1f32;
+    pub fn klein_bottle3(u:f32, v:f32) -> [f32; 3] {
+        let a = 8f32;
+        let n = 3f32;
+        let m = 1f32;
     
-    let x = (a + (0.5 * u * n).cos() * v.sin() - (0.5 * u * n).sin() * (2.0 * v).sin()) * (0.5 * u * m).cos();        
-    let y = (0.5 * u * n).sin() * v.sin() + (0.5 * u * n).cos() * (2.0 * v).sin();
-    let z = (a + (0.5 * u * n).cos() * v.sin() - (0.5 * u * n).sin() * (2.0 * v).sin()) * (0.5 * u * m).sin();   
-    [x, y, z]   
-}
+        let x = (a + (0.5 * u * n).cos() * v.sin() - (0.5 * u * n).sin() * (2.0 * v).sin()) * (0.5 * u * m).cos();        
+        let y = (0.5 * u * n).sin() * v.sin() + (0.5 * u * n).cos() * (2.0 * v).sin();
+        let z = (a + (0.5 * u * n).cos() * v.sin() - (0.5 * u * n).sin() * (2.0 * v).sin()) * (0.5 * u * m).sin();   
+        [x, y, z]   
+    }
 
-pub fn klein_bottle2(u:f32, v:f32) -> [f32; 3] {
-    let (mut x, mut z) = (0f32, 0f32);
-    let r = 4.0 * (1.0 - 0.5 * u.cos());
-    if u >= 0.0 && u <= PI {
-        x = 6.0 * u.cos() * (1.0 + u.sin()) + r * u.cos() * v.cos();
-        z = 16.0 * u.sin() + r * u.sin() * v.cos();
-    }  else if u > PI && u <= 2.0 * PI {
-        x = 6.0 * u.cos() * (1.0 + u.sin()) + r *(v + PI).cos();
-        z = 16.0 * u.sin();
-    }
-    let y = r * v.sin();
-    [x, y, z]
-}
+    pub fn klein_bottle2(u:f32, v:f32) -> [f32; 3] {
+        let (mut x, mut z) = (0.0 * u, 0.0 * u);  // zeros of the argument type
+        let r = 4.0 * (1.0 - 0.5 * u.cos());
+        if u >= 0.0 && u <= PI {
+            x = 6.0 * u.cos() * (1.0 + u.sin()) + r * u.cos() * v.cos();
+            z = 16.0 * u.sin() + r * u.sin() * v.cos();
+        }  else if u > PI && u <= 2.0 * PI {
+            x = 6.0 * u.cos() * (1.0 + u.sin()) + r *(v + PI).cos();
+            z = 16.0 * u.sin();
+        }
+        let y = r * v.sin();
+        [x, y, z]
+    }
 
-pub fn klein_bottle(u:f32, v:f32) -> [f32; 3] {
-    let x = 2.0/15.0*(3.0+5.0*u.cos()*u.sin())*v.sin(); 
+    pub fn klein_bottle(u:f32, v:f32) -> [f32; 3] {
+        let x = 2.0/15.0*(3.0+5.0*u.cos()*u.sin())*v.sin(); 
 
-    let y = -1.0/15.0*u.sin()*(3.0*v.cos()-3.0*(u.cos()).powf(2.0)*v.cos()-
-    48.0*(u.cos()).powf(4.0)*v.cos()+48.0*(u.cos()).powf(6.0)*v.cos()-
-    60.0*u.sin()+5.0*u.cos()*v.cos()*u.sin()-5.0*(u.cos()).powf(3.0)*v.cos()*u.sin()-
-    80.0*(u.cos()).powf(5.0)*v.cos()*u.sin()+80.0*(u.cos()).powf(7.0)*v.cos()*u.sin());
+        let y = -1.0/15.0*u.sin()*(3.0*v.cos()-3.0*(u.cos()).powf(2.0)*v.cos()-
+        48.0*(u.cos()).powf(4.0)*v.cos()+48.0*(u.cos()).powf(6.0)*v.cos()-
+        60.0*u.sin()+5.0*u.cos()*v.cos()*u.sin()-5.0*(u.cos()).powf(3.0)*v.cos()*u.sin()-
+        80.0*(u.cos()).powf(5.0)*v.cos()*u.sin()+80.0*(u.cos()).powf(7.0)*v.cos()*u.sin());
 
-    let z = -2.0/15.0*u.cos()*(3.0*v.cos()-30.0*u.sin() +
-    90.0*(u.cos()).powf(4.0)*u.sin()-60.0*(u.cos()).powf(6.0)*u.sin() + 5.0*u.cos()*v.cos()*u.sin());
+        let z = -2.0/15.0*u.cos()*(3.0*v.cos()-30.0*u.sin() +
+        90.0*(u.cos()).powf(4.0)*u.sin()-60.0*(u.cos()).powf(6.0)*u.sin() + 5.0*u.cos()*v.cos()*u.sin());
 
-    [x, y, z]
-}
+        [x, y, z]
+    }
 
-pub fn astroid(u:f32, v:f32) -> [f32; 3] {
-    let a = 1.5f32;
-    let x = a * (u.cos()).powf(3.0) * (v.cos()).powf(3.0);
-    let y = a * (u.sin()).powf(3.0);
-    let z = a * (u.sin()).powf(3.0) * (v.cos()).powf(3.0);
-    [x, y, z]
-}
+    pub fn astroid(u:f32, v:f32) -> [f32; 3] {
+        let a = 1.5f32;
+        let x = a * (u.cos()).powf(3.0) * (v.cos()).powf(3.0);
+        let y = a * (u.sin()).powf(3.0);
+        let z = a * (u.sin()).powf(3.0) * (v.cos()).powf(3.0);
+        [x, y, z]
+    }
 
-pub fn astroid2(u:f32, v:f32) -> [f32; 3] {
-    let x = (u.sin()).powf(3.0) * v.cos();
-    let y = (u.cos()).powf(3.0);
-    let z = (u.sin()).powf(3.0) * v.sin();
-    [x, y, z]
-}
+    pub fn astroid2(u:f32, v:f32) -> [f32; 3] {
+        let x = (u.sin()).powf(3.0) * v.cos();
+        let y = (u.cos()).powf(3.0);
+        let z = (u.sin()).powf(3.0) * v.sin();
+        [x, y, z]
+    }
 
-pub fn astroidal_torus(u:f32, v:f32) -> [f32; 3] {
-    let a = 2.0;
-    let b = 1.0;
-    let c = 7854.0f32;
-    let x = (a + b * (u.cos()).powf(3.0) * c.cos() - b * (u.sin()).powf(3.0) * c.sin()) * v.cos();
-    let y = b * (u.cos()).powf(3.0) * c.sin() + b * (u.sin()).powf(3.0) * c.cos();
-    let z = (a + b * (u.cos()).powf(3.0) * c.cos() - b * (u.sin()).powf(3.0) * c.sin()) * v.sin();
-    [x, y, z]
-}
+    pub fn astroidal_torus(u:f32, v:f32) -> [f32; 3] {
+        let a = 2.0;
+        let b = 1.0;
+        let c = 7854.0f32;
+        let x = (a + b * (u.cos()).powf(3.0) * c.cos() - b * (u.sin()).powf(3.0) * c.sin()) * v.cos();
+        let y = b * (u.cos()).powf(3.0) * c.sin() + b * (u.sin()).powf(3.0) * c.cos();
+        let z = (a + b * (u.cos()).powf(3.0) * c.cos() - b * (u.sin()).powf(3.0) * c.sin()) * v.sin();
+        [x, y, z]
+    }
 
-pub fn bohemian_dome(u:f32, v:f32) -> [f32; 3] {
-    let a = 0.7;
-    let x = a * u.cos();
-    let y = v.cos();
-    let z = a * u.sin() + v.sin();
-    [x, y, z]
-}
+    pub fn bohemian_dome(u:f32, v:f32) -> [f32; 3] {
+        let a = 0.7;
+        let x = a * u.cos();
+        let y = v.cos();
+        let z = a * u.sin() + v.sin();
+        [x, y, z]
+    }
 
-pub fn boy_shape(u:f32, v:f32) -> [f32; 3] {
-    let x = u.cos() * (1.0 / 3.0 * 2.0f32.sqrt() * u.cos() * (2.0 * v).cos() + 
-        2.0 / 3.0 * u.sin() * v.cos()) / (1.0 - 2.0f32.sqrt() * u.sin() * u.cos() * (3.0 * v).sin());
-    let y = u.cos() * u.cos() / (1.0 - 2.0f32.sqrt() * u.sin() * u.cos() * (3.0 * v).sin()) - 1.0;
-    let z = u.cos() * (1.0 / 3.0 * 2.0f32.sqrt() * u.cos() * (2.0 * v).sin() - 
-    2.0 / 3.0 * u.sin() * v.sin()) / (1.0 - 2.0f32.sqrt() * u.sin() * u.cos() * (3.0 * v).sin());
-    [x, y, z]
-}
+    pub fn boy_shape(u:f32, v:f32) -> [f32; 3] {
+        let x = u.cos() * (1.0 / 3.0 * 2.0f32.sqrt() * u.cos() * (2.0 * v).cos() + 
+            2.0 / 3.0 * u.sin() * v.cos()) / (1.0 - 2.0f32.sqrt() * u.sin() * u.cos() * (3.0 * v).sin());
+        let y = u.cos() * u.cos() / (1.0 - 2.0f32.sqrt() * u.sin() * u.cos() * (3.0 * v).sin()) - 1.0;
+        let z = u.cos() * (1.0 / 3.0 * 2.0f32.sqrt() * u.cos() * (2.0 * v).sin() - 
+        2.0 / 3.0 * u.sin() * v.sin()) / (1.0 - 2.0f32.sqrt() * u.sin() * u.cos() * (3.0 * v).sin());
+        [x, y, z]
+    }
 
-pub fn enneper(u:f32, v:f32) -> [f32; 3] {
-    let a = 1.0/3.0;
-    let x = a * u * (1.0 - u * u / 3.0 + v * v);
-    let y = a * (u * u - v * v);
-    let z = a * v * (1.0 - v * v / 3.0 + u * u);
-    [x, y, z]
-}
+    pub fn enneper(u:f32, v:f32) -> [f32; 3] {
+        let a = 1.0/3.0;
+        let x = a * u * (1.0 - u * u / 3.0 + v * v);
+        let y = a * (u * u - v * v);
+        let z = a * v * (1.0 - v * v / 3.0 + u * u);
+        [x, y, z]
+    }
 
-pub fn henneberg(u:f32, v:f32) -> [f32; 3] {
-    let x = u.sinh() * v.cos() - (3.0*u).sinh() * (3.0*v).cos()/3.0;
-    let y = (2.0*u).cosh() * (2.0*v).cos();
-    let z = u.sinh() * v.sin() - (3.0*u).sinh() * (3.0*v).sin()/3.0;
-    [x, y, z]
-}
+    pub fn henneberg(u:f32, v:f32) -> [f32; 3] {
+        let x = u.sinh() * v.cos() - (3.0*u).sinh() * (3.0*v).cos()/3.0;
+        let y = (2.0*u).cosh() * (2.0*v).cos();
+        let z = u.sinh() * v.sin() - (3.0*u).sinh() * (3.0*v).sin()/3.0;
+        [x, y, z]
+    }
 
-pub fn kiss(u:f32, v:f32) -> [f32; 3] {
-    let x = u * u * (1.0-u).sqrt() * v.cos();
-    let y = u;
-    let z = u * u * (1.0-u).sqrt() * v.sin();
-    [x, y, z]
-}
+    pub fn kiss(u:f32, v:f32) -> [f32; 3] {
+        let x = u * u * (1.0-u).sqrt() * v.cos();
+        let y = u;
+        let z = u * u * (1.0-u).sqrt() * v.sin();
+        [x, y, z]
+    }
 
-pub fn kuen(u:f32, v:f32) -> [f32; 3] {
-    let x = 2.0 * u * v.cos();
-    let y = 2.0 * (3.0 * v).cos();
-    let z = 2.0 * u * v.sin();
-    [x, y, z]
-}
+    pub fn kuen(u:f32, v:f32) -> [f32; 3] {
+        let x = 2.0 * u * v.cos();
+        let y = 2.0 * (3.0 * v).cos();
+        let z = 2.0 * u * v.sin();
+        [x, y, z]
+    }
 
-pub fn minimal(u:f32, v:f32) -> [f32; 3] {
-    let x = u - (2.0*u).exp() * (2.0 *v).cos() /2.0 ;
-    let y = 2.0 * u.exp() * v.cos();
-    let z = -(v + (2.0*u).exp() * (2.0 * v).sin()/2.0);
-    [x, y, z]
-}
+    pub fn minimal(u:f32, v:f32) -> [f32; 3] {
+        let x = u - (2.0*u).exp() * (2.0 *v).cos() /2.0 ;
+        let y = 2.0 * u.exp() * v.cos();
+        let z = -(v + (2.0*u).exp() * (2.0 * v).sin()/2.0);
+        [x, y, z]
+    }
 
-pub fn parabolic_cyclide(u:f32, v:f32) -> [f32; 3] {
-    let x = u * (0.5 + v*v)/(1.0 + u*u + v*v);
-    let y = 0.5 * (2.0*v*v + 0.5*(1.0 - u*u - v*v))/(1.0 + u*u + v*v);
-    let z = v * (1.0 + u*u -0.5)/(1.0 + u*u + v*v);
-    [x, y, z]
-}
+    pub fn parabolic_cyclide(u:f32, v:f32) -> [f32; 3] {
+        let x = u * (0.5 + v*v)/(1.0 + u*u + v*v);
+        let y = 0.5 * (2.0*v*v + 0.5*(1.0 - u*u - v*v))/(1.0 + u*u + v*v);
+        let z = v * (1.0 + u*u -0.5)/(1.0 + u*u + v*v);
+        [x, y, z]
+    }
 
-pub fn pear(u:f32, v:f32) -> [f32; 3] {
-    let x = u * (u * (1.0 - u)).sqrt() * v.cos();
-    let y = -u;
-    let z = u * (u * (1.0 - u)).sqrt() * v.sin();
-    [x, y, z]
-}
+    pub fn pear(u:f32, v:f32) -> [f32; 3] {
+        let x = u * (u * (1.0 - u)).sqrt() * v.cos();
+        let y = -u;
+        let z = u * (u * (1.0 - u)).sqrt() * v.sin();
+        [x, y, z]
+    }
 
-pub fn plucker_conoid(u:f32, v:f32) -> [f32; 3] {
-    let x = 2.0 * u * v.cos();
-    let y = 2.0 * (3.0 * v).cos();
-    let z = 2.0 * u * v.sin();
-    [x, y, z]
-}
+    pub fn plucker_conoid(u:f32, v:f32) -> [f32; 3] {
+        let x = 2.0 * u * v.cos();
+        let y = 2.0 * (3.0 * v).cos();
+        let z = 2.0 * u * v.sin();
+        [x, y, z]
+    }
 
-pub fn steiner(u:f32, v:f32) -> [f32; 3] {
-    let x = u.cos() * v.cos() * v.sin();
-    let y = u.cos() * u.sin() * (v.cos()).powf(2.0);
-    let z = u.sin() * v.cos() * v.sin();
-    [x, y, z]
+    pub fn steiner(u:f32, v:f32) -> [f32; 3] {
+        let x = u.cos() * v.cos() * v.sin();
+        let y = u.cos() * u.sin() * (v.cos()).powf(2.0);
+        let z = u.sin() * v.cos() * v.sin();
+        [x, y, z]
+    }
 }
 
 pub fn sinc(x:f32, z:f32, t:f32) -> [f32; 3] {
@@ -236,3 +254,22 @@ pub fn poles(x:f32, z:f32, t:f32) -> [f32; 3] {
     let y =  x*z/(((x-a)*(x-a)*(x-a)).abs() + (z- 2.0*a)*(z- 2.0*a) + 2.0);
     [x, y, z]
 }
+
+// implicit surfaces f(x, y, z, t) = 0, positive outside, for implicit_surface
+pub fn gyroid(x:f32, y:f32, z:f32, _t:f32) -> f32 {
+    x.sin() * y.cos() + y.sin() * z.cos() + z.sin() * x.cos()
+}
+
+pub fn metaballs(x:f32, y:f32, z:f32, t:f32) -> f32 {
+    let balls = [
+        ([0.8 * t.cos(), 0.3 * (2.0*t).sin(), 0.0], 0.6),
+        ([-0.6, 0.7 * t.sin(), 0.5 * t.cos()], 0.5),
+        ([0.0, -0.6, -0.4 * (1.5*t).sin()], 0.55),
+    ];
+    let mut sum = 0.0;
+    for (c, r) in balls {
+        let d2 = (x-c[0])*(x-c[0]) + (y-c[1])*(y-c[1]) + (z-c[2])*(z-c[2]);
+        sum += r * r / d2.max(1e-6);
+    }
+    1.0 - sum
+}
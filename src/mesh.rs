@@ -0,0 +1,402 @@
+#![allow(dead_code)]
+use anyhow::{bail, Context, Result};
+use cgmath::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::vertex_data::create_mikktspace_tangents;
+use super::curvature::{mesh_curvature, Curvature};
+use super::topology::{mesh_topology, weld_by_position, weld_tolerance, Topology};
+use super::lod::{simplify_chain, LodChain};
+
+// Index format requested from a builder. Auto uses 16-bit indices and switches to 32-bit
+// indices once the vertex count no longer fits into a u16.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexFormat {
+    #[default]
+    Auto,
+    U16,
+    U32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Default for Indices {
+    fn default() -> Self {
+        Indices::U16(vec![])
+    }
+}
+
+impl Indices {
+    pub fn new(indices: Vec<u32>, vertex_count: usize, format: IndexFormat) -> Result<Self> {
+        match format {
+            IndexFormat::Auto => Ok(Self::auto(indices, vertex_count)),
+            IndexFormat::U32 => Ok(Indices::U32(indices)),
+            IndexFormat::U16 => {
+                if vertex_count > u16::MAX as usize + 1 {
+                    bail!("{} vertices cannot be addressed with 16-bit indices", vertex_count);
+                }
+                Ok(Indices::U16(indices.iter().map(|&i| i as u16).collect()))
+            }
+        }
+    }
+
+    // picks 16-bit indices whenever the vertex count allows it
+    pub fn auto(indices: Vec<u32>, vertex_count: usize) -> Self {
+        if vertex_count <= u16::MAX as usize + 1 {
+            Indices::U16(indices.iter().map(|&i| i as u16).collect())
+        } else {
+            Indices::U32(indices)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(v) => v.len(),
+            Indices::U32(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn format(&self) -> IndexFormat {
+        match self {
+            Indices::U16(_) => IndexFormat::U16,
+            Indices::U32(_) => IndexFormat::U32,
+        }
+    }
+
+    pub fn get(&self, i: usize) -> usize {
+        match self {
+            Indices::U16(v) => v[i] as usize,
+            Indices::U32(v) => v[i] as usize,
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = usize> + '_> {
+        match self {
+            Indices::U16(v) => Box::new(v.iter().map(|&i| i as usize)),
+            Indices::U32(v) => Box::new(v.iter().map(|&i| i as usize)),
+        }
+    }
+
+    pub fn to_u32(&self) -> Vec<u32> {
+        self.iter().map(|i| i as u32).collect()
+    }
+
+    pub fn to_u16(&self) -> Result<Vec<u16>> {
+        match self {
+            Indices::U16(v) => Ok(v.clone()),
+            Indices::U32(v) => {
+                if let Some(i) = v.iter().find(|&&i| i > u16::MAX as u32) {
+                    bail!("index {} does not fit into a 16-bit index buffer", i);
+                }
+                Ok(v.iter().map(|&i| i as u16).collect())
+            }
+        }
+    }
+
+    // raw bytes for uploading into an index buffer
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Indices::U16(v) => bytemuck::cast_slice(v),
+            Indices::U32(v) => bytemuck::cast_slice(v),
+        }
+    }
+}
+
+impl From<Vec<u16>> for Indices {
+    fn from(v: Vec<u16>) -> Self {
+        Indices::U16(v)
+    }
+}
+
+impl From<Vec<u32>> for Indices {
+    fn from(v: Vec<u32>) -> Self {
+        Indices::U32(v)
+    }
+}
+
+// Where a texture image comes from: an image file such as assets/brick.png, or encoded image
+// bytes embedded in a model file (e.g. a .glb).
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureSource {
+    File(PathBuf),
+    Embedded { name: String, mime_type: String, data: Vec<u8> },
+}
+
+impl TextureSource {
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            TextureSource::File(p) => Some(p),
+            TextureSource::Embedded { .. } => None,
+        }
+    }
+
+    // the encoded image bytes (png or jpeg)
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        match self {
+            TextureSource::File(p) => fs::read(p).with_context(|| format!("failed to read {}", p.display())),
+            TextureSource::Embedded { data, .. } => Ok(data.clone()),
+        }
+    }
+
+    pub fn load(&self) -> Result<image::DynamicImage> {
+        match self {
+            TextureSource::File(p) => image::open(p).with_context(|| format!("failed to load {}", p.display())),
+            TextureSource::Embedded { name, data, .. } => image::load_from_memory(data)
+                .with_context(|| format!("failed to decode embedded image '{}'", name)),
+        }
+    }
+
+    // Returns a file path that can be handed to the same texture loading code the examples
+    // use for assets/*.png. Embedded images are written into dir first.
+    pub fn to_file<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf> {
+        match self {
+            TextureSource::File(p) => Ok(p.clone()),
+            TextureSource::Embedded { name, mime_type, data } => {
+                let ext = if mime_type == "image/jpeg" { "jpg" } else { "png" };
+                let stem: String = name.chars()
+                    .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+                    .collect();
+                let path = dir.as_ref().join(format!("{}.{}", stem, ext));
+                fs::write(&path, data).with_context(|| format!("failed to write {}", path.display()))?;
+                Ok(path)
+            }
+        }
+    }
+}
+
+impl From<PathBuf> for TextureSource {
+    fn from(p: PathBuf) -> Self {
+        TextureSource::File(p)
+    }
+}
+
+impl From<&str> for TextureSource {
+    fn from(p: &str) -> Self {
+        TextureSource::File(PathBuf::from(p))
+    }
+}
+
+// Blinn-Phong material parameters and texture maps, as used by the examples' shaders and
+// by the .mtl files read in obj::load_obj.
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub diffuse_texture: Option<TextureSource>,   // base color, map_Kd
+    pub normal_texture: Option<TextureSource>,    // map_Bump, bump or norm
+    pub height_texture: Option<TextureSource>,    // parallax height map, disp or map_disp
+    pub specular_texture: Option<TextureSource>,  // map_Ks
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            ambient: [0.1, 0.1, 0.1],
+            diffuse: [0.8, 0.8, 0.8],
+            specular: [0.1, 0.1, 0.1],
+            shininess: 5.0,
+            diffuse_texture: None,
+            normal_texture: None,
+            height_texture: None,
+            specular_texture: None,
+        }
+    }
+}
+
+impl Material {
+    // from the scalar [ambient, diffuse, specular, shininess] intensities written into the
+    // examples' material uniform buffers, e.g. [0.1, 0.4, 0.1, 5.0]
+    pub fn blinn_phong(ambient: f32, diffuse: f32, specular: f32, shininess: f32) -> Self {
+        Self {
+            ambient: [ambient; 3],
+            diffuse: [diffuse; 3],
+            specular: [specular; 3],
+            shininess,
+            ..Default::default()
+        }
+    }
+}
+
+// Mesh data produced by the builders in vertex_data and surface_data. Only positions are
+// mandatory; every other attribute channel is optional so that a builder can leave out
+// what it does not generate (for example, the cylinder has no uvs).
+#[derive(Default, Debug, Clone)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Option<Vec<[f32; 3]>>,
+    pub uvs: Option<Vec<[f32; 2]>>,
+    pub colors: Option<Vec<[f32; 3]>>,
+    pub tangents: Option<Vec<[f32; 4]>>,  // xyz: tangent, w: bitangent sign
+    pub indices: Indices,   // triangle list indices
+    pub indices2: Indices,  // line list (wireframe) indices
+}
+
+impl Mesh {
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    // Checks that every attribute channel matches the vertex count and that all indices are
+    // in range.
+    pub fn validate(&self) -> Result<()> {
+        let n = self.vertex_count();
+        let channels = [
+            ("normals", self.normals.as_ref().map(|v| v.len())),
+            ("uvs", self.uvs.as_ref().map(|v| v.len())),
+            ("colors", self.colors.as_ref().map(|v| v.len())),
+            ("tangents", self.tangents.as_ref().map(|v| v.len())),
+        ];
+        for (name, len) in channels {
+            if let Some(len) = len {
+                if len != n {
+                    bail!("mesh has {} {} for {} positions", len, name, n);
+                }
+            }
+        }
+        if !self.indices.len().is_multiple_of(3) {
+            bail!("triangle index count {} is not a multiple of 3", self.indices.len());
+        }
+        if let Some(i) = self.indices.iter().chain(self.indices2.iter()).find(|&i| i >= n) {
+            bail!("index {} out of range for {} vertices", i, n);
+        }
+        Ok(())
+    }
+
+    // Area-weighted smooth normals, for meshes loaded without normals.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vector3::new(0.0f32, 0.0, 0.0); self.positions.len()];
+        for i in (0..self.indices.len()).step_by(3) {
+            let c = [self.indices.get(i), self.indices.get(i+1), self.indices.get(i+2)];
+            let p0 = Vector3::from(self.positions[c[0]]);
+            let p1 = Vector3::from(self.positions[c[1]]);
+            let p2 = Vector3::from(self.positions[c[2]]);
+            let n = (p1 - p0).cross(p2 - p0);
+            for k in c {
+                normals[k] += n;
+            }
+        }
+        self.normals = Some(normals.iter().map(|n| {
+            if n.magnitude2() > 0.0 { n.normalize().into() } else { [0.0, 1.0, 0.0] }
+        }).collect());
+    }
+
+    // Discrete curvature at every vertex, see curvature::mesh_curvature.
+    pub fn curvatures(&self) -> Vec<Curvature> {
+        mesh_curvature(&self.positions, self.normals.as_deref(), &self.indices.to_u32())
+    }
+
+    // Connectivity of the mesh with vertices at the same position, up to weld_tolerance, merged
+    // so that uv seams and split normals do not count as boundaries.
+    pub fn topology(&self) -> Topology {
+        let weld = weld_by_position(&self.positions, weld_tolerance(&self.positions));
+        let indices: Vec<u32> = self.indices.iter().map(|i| weld[i]).collect();
+        mesh_topology(&indices)
+    }
+
+    // Levels of detail by edge collapse, see lod::simplify_chain.
+    pub fn lod_chain(&self, levels:usize, max_error:f32) -> LodChain<Mesh> {
+        simplify_chain(self, levels, max_error)
+    }
+
+    // Fills the tangents channel with MikkTSpace tangents; requires normals and uvs. Vertices
+    // whose triangles need different tangents, as on mirrored uv seams, are split; the
+    // wireframe keeps the original vertices.
+    pub fn generate_tangents(&mut self) -> Result<()> {
+        let (normals, uvs) = match (&self.normals, &self.uvs) {
+            (Some(n), Some(uv)) => (n, uv),
+            _ => bail!("tangent generation requires normals and uvs"),
+        };
+        let m = create_mikktspace_tangents(&self.positions, normals, uvs, &self.indices.to_u32())?;
+        let n = self.vertex_count();
+        fn copy<T:Copy>(v:&mut Vec<T>, remap:&[u32]) {
+            for &i in remap {
+                v.push(v[i as usize]);
+            }
+        }
+        let split = &m.remap[n..];
+        copy(&mut self.positions, split);
+        for channel in [&mut self.normals, &mut self.colors].into_iter().flatten() {
+            copy(channel, split);
+        }
+        if let Some(uvs) = &mut self.uvs {
+            copy(uvs, split);
+        }
+        let count = self.vertex_count();
+        self.indices = match self.indices.format() {
+            IndexFormat::U32 => Indices::U32(m.indices),
+            _ => Indices::auto(m.indices, count),
+        };
+        self.tangents = Some(m.tangents);
+        Ok(())
+    }
+
+    // Converts both index buffers to the requested format. Forcing U16 on a mesh with more
+    // than 65536 vertices is an error rather than a silent wraparound.
+    pub fn set_index_format(&mut self, format: IndexFormat) -> Result<()> {
+        let n = self.vertex_count();
+        self.indices = Indices::new(self.indices.to_u32(), n, format)?;
+        self.indices2 = Indices::new(self.indices2.to_u32(), n, format)?;
+        Ok(())
+    }
+}
+
+// region: tuple conversions
+// These keep callers written against the old tuple-returning builders working, e.g.
+// let (pos, _, norm, uv, ind, _) = vd::create_cube_data(2.0).try_into()?;
+// The old tuples carry 16-bit indices, so the conversion fails on meshes that need 32-bit
+// indices; keep the Mesh and its Indices to handle those.
+
+// (positions, colors, normals, uvs, indices, indices2), as from create_cube_data
+impl TryFrom<Mesh> for (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<u16>, Vec<u16>) {
+    type Error = anyhow::Error;
+
+    fn try_from(m: Mesh) -> Result<Self> {
+        Ok((m.positions, m.colors.unwrap_or_default(), m.normals.unwrap_or_default(),
+         m.uvs.unwrap_or_default(), m.indices.to_u16()?, m.indices2.to_u16()?))
+    }
+}
+
+// (positions, normals, uvs, indices, indices2), as from create_sphere_data
+impl TryFrom<Mesh> for (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<u16>, Vec<u16>) {
+    type Error = anyhow::Error;
+
+    fn try_from(m: Mesh) -> Result<Self> {
+        Ok((m.positions, m.normals.unwrap_or_default(), m.uvs.unwrap_or_default(),
+         m.indices.to_u16()?, m.indices2.to_u16()?))
+    }
+}
+
+// (positions, normals, indices, indices2), as from create_torus_data
+impl TryFrom<Mesh> for (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<u16>, Vec<u16>) {
+    type Error = anyhow::Error;
+
+    fn try_from(m: Mesh) -> Result<Self> {
+        Ok((m.positions, m.normals.unwrap_or_default(), m.indices.to_u16()?, m.indices2.to_u16()?))
+    }
+}
+
+// (positions, indices, indices2), as from create_cylinder_data
+impl TryFrom<Mesh> for (Vec<[f32; 3]>, Vec<u16>, Vec<u16>) {
+    type Error = anyhow::Error;
+
+    fn try_from(m: Mesh) -> Result<Self> {
+        Ok((m.positions, m.indices.to_u16()?, m.indices2.to_u16()?))
+    }
+}
+// endregion: tuple conversions
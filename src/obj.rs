@@ -0,0 +1,236 @@
+#![allow(dead_code)]
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+use super::mesh::{Indices, Material, Mesh, TextureSource};
+
+// One group of faces sharing a material ("usemtl" block or "o"/"g" object in the .obj file).
+#[derive(Debug, Clone)]
+pub struct ObjModel {
+    pub name: String,
+    pub mesh: Mesh,
+    pub material_id: Option<usize>,  // index into the returned materials
+}
+
+// Loads an .obj file together with the .mtl libraries it references. Texture paths in the
+// materials are resolved relative to the directory of the .obj file. Faces are triangulated
+// and every vertex gets a single index for position, normal and uv, so the meshes can be
+// passed straight to vertex_data::create_tangent_data. Missing normals are computed from the
+// faces; uvs are flipped vertically because .obj puts the texture origin at the bottom left
+// while wgpu samples from the top left. A missing or broken .mtl library is logged as a
+// warning, and the models then use the default material (material_id None).
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<(Vec<ObjModel>, Vec<Material>)> {
+    let path = path.as_ref();
+    let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+        .with_context(|| format!("failed to load {}", path.display()))?;
+    let materials = materials.unwrap_or_else(|e| {
+        log::warn!("failed to load materials for {}: {}", path.display(), e);
+        vec![]
+    });
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let materials: Vec<Material> = materials.iter().map(|m| convert_material(m, dir)).collect();
+
+    let mut out: Vec<ObjModel> = vec![];
+    for m in models {
+        out.push(ObjModel { 
+            name: m.name, 
+            material_id: m.mesh.material_id.filter(|&id| id < materials.len()),
+            mesh: convert_mesh(m.mesh)?,
+        });
+    }
+    Ok((out, materials))
+}
+
+fn convert_mesh(m: tobj::Mesh) -> Result<Mesh> {
+    if m.positions.is_empty() {
+        bail!("model has no vertex positions");
+    }
+    let positions: Vec<[f32; 3]> = m.positions.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect();
+    let normals = if m.normals.is_empty() { 
+        None 
+    } else {
+        Some(m.normals.chunks_exact(3).map(|n| [n[0], n[1], n[2]]).collect())
+    };
+    let uvs = if m.texcoords.is_empty() { 
+        None 
+    } else {
+        Some(m.texcoords.chunks_exact(2).map(|t| [t[0], 1.0 - t[1]]).collect())
+    };
+    let colors = if m.vertex_color.is_empty() { 
+        None 
+    } else {
+        Some(m.vertex_color.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect())
+    };
+
+    // wireframe indices: the three edges of every triangle
+    let mut indices2: Vec<u32> = vec![];
+    for t in m.indices.chunks_exact(3) {
+        indices2.extend([t[0], t[1], t[1], t[2], t[2], t[0]]);
+    }
+
+    let n = positions.len();
+    let mut mesh = Mesh {
+        positions,
+        normals,
+        uvs,
+        colors,
+        tangents: None,
+        indices: Indices::auto(m.indices, n),
+        indices2: Indices::auto(indices2, n),
+    };
+    if mesh.normals.is_none() {
+        mesh.compute_normals();
+    }
+    Ok(mesh)
+}
+
+fn convert_material(m: &tobj::Material, dir: &Path) -> Material {
+    let texture = |name: &Option<String>| name.as_ref().map(|s| texture_path(s, dir));
+    let param = |keys: &[&str]| keys.iter()
+        .find_map(|k| m.unknown_param.get(*k))
+        .map(|s| texture_path(s, dir));
+    let d = Material::default();
+    Material {
+        name: m.name.clone(),
+        ambient: m.ambient.unwrap_or(d.ambient),
+        diffuse: m.diffuse.unwrap_or(d.diffuse),
+        specular: m.specular.unwrap_or(d.specular),
+        shininess: m.shininess.unwrap_or(d.shininess),
+        diffuse_texture: texture(&m.diffuse_texture),
+        normal_texture: texture(&m.normal_texture).or_else(|| param(&["norm"])),
+        height_texture: param(&["disp", "map_disp"]),
+        specular_texture: texture(&m.specular_texture),
+    }
+}
+
+// Texture statements may carry options such as "-bm 1.0 brick-normal.png"; the file name is
+// the last token.
+fn texture_path(statement: &str, dir: &Path) -> TextureSource {
+    let file = statement.split_whitespace().last().unwrap_or(statement);
+    TextureSource::File(dir.join(file.replace('\\', "/")))
+}
+
+// Groups the models by material, e.g. to build one texture bind group per material.
+pub fn models_by_material(models: &[ObjModel]) -> HashMap<Option<usize>, Vec<&ObjModel>> {
+    let mut map: HashMap<Option<usize>, Vec<&ObjModel>> = HashMap::new();
+    for m in models {
+        map.entry(m.material_id).or_default().push(m);
+    }
+    map
+}
+
+// Writes a mesh as .obj text. Vertex colors are written as the widely supported
+// "v x y z r g b" extension, uvs are flipped back to the .obj convention and all attributes
+// share the mesh's single index.
+pub fn write_obj<W: Write>(w: &mut W, mesh: &Mesh) -> Result<()> {
+    mesh.validate()?;
+
+    for (i, p) in mesh.positions.iter().enumerate() {
+        match &mesh.colors {
+            Some(c) => writeln!(w, "v {} {} {} {} {} {}", p[0], p[1], p[2], c[i][0], c[i][1], c[i][2])?,
+            None => writeln!(w, "v {} {} {}", p[0], p[1], p[2])?,
+        }
+    }
+    if let Some(uvs) = &mesh.uvs {
+        for t in uvs {
+            writeln!(w, "vt {} {}", t[0], 1.0 - t[1])?;
+        }
+    }
+    if let Some(normals) = &mesh.normals {
+        for v in normals {
+            writeln!(w, "vn {} {} {}", v[0], v[1], v[2])?;
+        }
+    }
+
+    // .obj indices start at 1
+    let vertex = |i: usize| match (mesh.uvs.is_some(), mesh.normals.is_some()) {
+        (true, true) => format!("{0}/{0}/{0}", i + 1),
+        (true, false) => format!("{0}/{0}", i + 1),
+        (false, true) => format!("{0}//{0}", i + 1),
+        (false, false) => format!("{}", i + 1),
+    };
+    for i in (0..mesh.indices.len()).step_by(3) {
+        writeln!(w, "f {} {} {}", vertex(mesh.indices.get(i)), vertex(mesh.indices.get(i+1)),
+            vertex(mesh.indices.get(i+2)))?;
+    }
+    Ok(())
+}
+
+pub fn save_obj<P: AsRef<Path>>(path: P, mesh: &Mesh) -> Result<()> {
+    let path = path.as_ref();
+    let file = File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut w = BufWriter::new(file);
+    write_obj(&mut w, mesh)?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex_data::{create_cube_data, create_sphere_data};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("wgpu_textures_{}_{}.obj", std::process::id(), name))
+    }
+
+    fn close<const N: usize>(a: [f32; N], b: [f32; N]) -> bool {
+        (0..N).all(|k| (a[k] - b[k]).abs() <= 1e-5)
+    }
+
+    // load_obj gives every position/uv/normal combination its own index, so the triangles are
+    // compared corner by corner rather than index by index
+    #[test]
+    fn round_trip() {
+        for (name, mesh) in [("cube", create_cube_data(2.0)), ("sphere", create_sphere_data(1.0, 12, 8))] {
+            let path = temp_path(name);
+            save_obj(&path, &mesh).unwrap();
+            let (models, materials) = load_obj(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert!(materials.is_empty());
+            assert_eq!(models.len(), 1);
+
+            let read = &models[0].mesh;
+            assert_eq!(read.triangle_count(), mesh.triangle_count());
+            let (uvs, read_uvs) = (mesh.uvs.as_ref().unwrap(), read.uvs.as_ref().unwrap());
+            let (normals, read_normals) = (mesh.normals.as_ref().unwrap(), read.normals.as_ref().unwrap());
+            for (i, j) in mesh.indices.iter().zip(read.indices.iter()) {
+                assert!(close(mesh.positions[i], read.positions[j]), "{}: position of vertex {}", name, i);
+                assert!(close(uvs[i], read_uvs[j]), "{}: uv of vertex {}", name, i);
+                assert!(close(normals[i], read_normals[j]), "{}: normal of vertex {}", name, i);
+            }
+        }
+    }
+
+    // .obj stores v from the bottom of the texture
+    #[test]
+    fn flipped_v() {
+        let mesh = create_cube_data(2.0);
+        let mut bytes: Vec<u8> = vec![];
+        write_obj(&mut bytes, &mesh).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        let vt: Vec<[f32; 2]> = text.lines().filter_map(|l| l.strip_prefix("vt "))
+            .map(|l| {
+                let v: Vec<f32> = l.split_whitespace().map(|t| t.parse().unwrap()).collect();
+                [v[0], v[1]]
+            }).collect();
+        for (t, uv) in vt.iter().zip(mesh.uvs.as_ref().unwrap()) {
+            assert!(close(*t, [uv[0], 1.0 - uv[1]]));
+        }
+    }
+
+    // a missing material library leaves the geometry and the default material
+    #[test]
+    fn missing_material_library() {
+        let path = temp_path("missing_mtl");
+        std::fs::write(&path, "mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n").unwrap();
+        let (models, materials) = load_obj(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(materials.is_empty());
+        assert_eq!(models[0].mesh.triangle_count(), 1);
+        assert_eq!(models[0].material_id, None);
+    }
+}
@@ -0,0 +1,22 @@
+#![allow(dead_code)]
+
+// f(0), ..., f(n - 1) in order. With the parallel feature the calls are spread across the
+// rayon thread pool; every element is still computed by the same call, so the output is
+// identical to the serial path.
+pub(crate) fn map_range<T, F>(n:usize, f:F) -> Vec<T>
+where T: Send, F: Fn(usize) -> T + Send + Sync {
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        (0..n).into_par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        (0..n).map(f).collect()
+    }
+}
+
+// Whether map_range runs across threads.
+pub fn enabled() -> bool {
+    cfg!(feature = "parallel")
+}
@@ -0,0 +1,405 @@
+#![allow(dead_code)]
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+use super::mesh::{Indices, Mesh};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlyFormat {
+    #[default]
+    BinaryLittleEndian,
+    BinaryBigEndian,
+    Ascii,
+}
+
+impl PlyFormat {
+    fn name(&self) -> &'static str {
+        match self {
+            PlyFormat::BinaryLittleEndian => "binary_little_endian",
+            PlyFormat::BinaryBigEndian => "binary_big_endian",
+            PlyFormat::Ascii => "ascii",
+        }
+    }
+}
+
+// region: writer
+// Writes the mesh with x/y/z, nx/ny/nz, s/t and red/green/blue vertex properties (the ones
+// present in the mesh) and a triangle face list. Colors are stored as uchar, which is what
+// MeshLab and Blender expect, and t is flipped to the bottom-left texture origin of PLY, as
+// write_obj does for .obj files.
+pub fn write_ply<W: Write>(w: &mut W, mesh: &Mesh, format: PlyFormat) -> Result<()> {
+    mesh.validate()?;
+
+    writeln!(w, "ply")?;
+    writeln!(w, "format {} 1.0", format.name())?;
+    writeln!(w, "comment generated by wgpu_textures")?;
+    writeln!(w, "element vertex {}", mesh.vertex_count())?;
+    for p in ["x", "y", "z"] {
+        writeln!(w, "property float {}", p)?;
+    }
+    if mesh.normals.is_some() {
+        for p in ["nx", "ny", "nz"] {
+            writeln!(w, "property float {}", p)?;
+        }
+    }
+    if mesh.uvs.is_some() {
+        for p in ["s", "t"] {
+            writeln!(w, "property float {}", p)?;
+        }
+    }
+    if mesh.colors.is_some() {
+        for p in ["red", "green", "blue"] {
+            writeln!(w, "property uchar {}", p)?;
+        }
+    }
+    writeln!(w, "element face {}", mesh.triangle_count())?;
+    writeln!(w, "property list uchar uint vertex_indices")?;
+    writeln!(w, "end_header")?;
+
+    let to_u8 = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    for i in 0..mesh.vertex_count() {
+        let mut floats: Vec<f32> = mesh.positions[i].to_vec();
+        if let Some(n) = &mesh.normals {
+            floats.extend(n[i]);
+        }
+        if let Some(uv) = &mesh.uvs {
+            floats.extend([uv[i][0], 1.0 - uv[i][1]]);
+        }
+        let bytes: Vec<u8> = match &mesh.colors {
+            Some(c) => c[i].iter().map(|&c| to_u8(c)).collect(),
+            None => vec![],
+        };
+
+        match format {
+            PlyFormat::Ascii => {
+                let mut line: Vec<String> = floats.iter().map(|f| f.to_string()).collect();
+                line.extend(bytes.iter().map(|b| b.to_string()));
+                writeln!(w, "{}", line.join(" "))?;
+            }
+            PlyFormat::BinaryLittleEndian => {
+                for f in floats {
+                    w.write_all(&f.to_le_bytes())?;
+                }
+                w.write_all(&bytes)?;
+            }
+            PlyFormat::BinaryBigEndian => {
+                for f in floats {
+                    w.write_all(&f.to_be_bytes())?;
+                }
+                w.write_all(&bytes)?;
+            }
+        }
+    }
+
+    for i in (0..mesh.indices.len()).step_by(3) {
+        let t = [mesh.indices.get(i) as u32, mesh.indices.get(i+1) as u32, mesh.indices.get(i+2) as u32];
+        match format {
+            PlyFormat::Ascii => writeln!(w, "3 {} {} {}", t[0], t[1], t[2])?,
+            PlyFormat::BinaryLittleEndian => {
+                w.write_all(&[3u8])?;
+                for k in t {
+                    w.write_all(&k.to_le_bytes())?;
+                }
+            }
+            PlyFormat::BinaryBigEndian => {
+                w.write_all(&[3u8])?;
+                for k in t {
+                    w.write_all(&k.to_be_bytes())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn save_ply<P: AsRef<Path>>(path: P, mesh: &Mesh, format: PlyFormat) -> Result<()> {
+    let path = path.as_ref();
+    let file = File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut w = BufWriter::new(file);
+    write_ply(&mut w, mesh, format)?;
+    w.flush()?;
+    Ok(())
+}
+// endregion: writer
+
+// region: reader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScalarType {
+    I8, U8, I16, U16, I32, U32, F32, F64,
+}
+
+impl ScalarType {
+    fn parse(s: &str) -> Result<Self> {
+        Ok(match s {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => bail!("unknown PLY property type '{}'", s),
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Property {
+    name: String,
+    ty: ScalarType,
+    list_count: Option<ScalarType>,  // Some for list properties
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// Reads values one at a time from either the ascii or the binary body of the file.
+struct ValueReader<R: BufRead> {
+    r: R,
+    format: PlyFormat,
+    tokens: Vec<String>,
+}
+
+impl<R: BufRead> ValueReader<R> {
+    fn next(&mut self, ty: ScalarType) -> Result<f64> {
+        if self.format == PlyFormat::Ascii {
+            while self.tokens.is_empty() {
+                let mut line = String::new();
+                if self.r.read_line(&mut line)? == 0 {
+                    bail!("unexpected end of PLY data");
+                }
+                self.tokens = line.split_whitespace().rev().map(String::from).collect();
+            }
+            let t = self.tokens.pop().unwrap();
+            return t.parse::<f64>().with_context(|| format!("invalid PLY value '{}'", t));
+        }
+
+        let mut buf = [0u8; 8];
+        let b = &mut buf[..ty.size()];
+        self.r.read_exact(b).context("unexpected end of PLY data")?;
+        if self.format == PlyFormat::BinaryBigEndian {
+            b.reverse();
+        }
+        Ok(match ty {
+            ScalarType::I8 => b[0] as i8 as f64,
+            ScalarType::U8 => b[0] as f64,
+            ScalarType::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            ScalarType::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            ScalarType::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::F64 => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+        })
+    }
+}
+
+fn read_header<R: BufRead>(r: &mut R) -> Result<(PlyFormat, Vec<Element>)> {
+    let mut line = String::new();
+    r.read_line(&mut line)?;
+    if line.trim() != "ply" {
+        bail!("not a PLY file");
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    loop {
+        line.clear();
+        if r.read_line(&mut line)? == 0 {
+            bail!("PLY header is missing end_header");
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["end_header"] => break,
+            ["format", f, _] => format = Some(match *f {
+                "ascii" => PlyFormat::Ascii,
+                "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                _ => bail!("unknown PLY format '{}'", f),
+            }),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().with_context(|| format!("invalid element count '{}'", count))?,
+                properties: vec![],
+            }),
+            ["property", "list", count_ty, ty, name] => {
+                let e = elements.last_mut().context("PLY property before any element")?;
+                e.properties.push(Property {
+                    name: name.to_string(),
+                    ty: ScalarType::parse(ty)?,
+                    list_count: Some(ScalarType::parse(count_ty)?),
+                });
+            }
+            ["property", ty, name] => {
+                let e = elements.last_mut().context("PLY property before any element")?;
+                e.properties.push(Property { name: name.to_string(), ty: ScalarType::parse(ty)?, list_count: None });
+            }
+            _ => {}  // comment, obj_info or blank line
+        }
+    }
+    Ok((format.context("PLY header has no format line")?, elements))
+}
+
+// Reads positions, normals, uvs, colors and faces from an ascii or binary PLY file. Polygons
+// with more than three vertices are split into triangle fans, and t is flipped back to the
+// top-left texture origin of wgpu.
+pub fn read_ply<R: BufRead>(mut r: R) -> Result<Mesh> {
+    let (format, elements) = read_header(&mut r)?;
+    let mut values = ValueReader { r, format, tokens: vec![] };
+
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut normals: Vec<[f32; 3]> = vec![];
+    let mut uvs: Vec<[f32; 2]> = vec![];
+    let mut colors: Vec<[f32; 3]> = vec![];
+    let mut indices: Vec<u32> = vec![];
+
+    for e in &elements {
+        let find = |names: &[&str]| e.properties.iter().position(|p| names.contains(&p.name.as_str()));
+        let pos = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let norm = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let uv = [find(&["s", "u", "texture_u"]), find(&["t", "v", "texture_v"])];
+        let col = [find(&["red"]), find(&["green"]), find(&["blue"])];
+        let face = find(&["vertex_indices", "vertex_index"]);
+
+        for _ in 0..e.count {
+            let mut scalars: Vec<f64> = vec![0.0; e.properties.len()];
+            let mut list: Vec<u32> = vec![];
+            for (k, p) in e.properties.iter().enumerate() {
+                match p.list_count {
+                    Some(count_ty) => {
+                        let n = values.next(count_ty)? as usize;
+                        // n comes from the file, so don't reserve memory for it up front
+                        let mut items: Vec<u32> = vec![];
+                        for _ in 0..n {
+                            items.push(values.next(p.ty)? as u32);
+                        }
+                        if Some(k) == face {
+                            list = items;
+                        }
+                    }
+                    None => scalars[k] = values.next(p.ty)?,
+                }
+            }
+
+            if e.name == "vertex" {
+                let get = |i: Option<usize>| i.map(|i| scalars[i] as f32).unwrap_or(0.0);
+                positions.push([get(pos[0]), get(pos[1]), get(pos[2])]);
+                if norm.iter().all(|i| i.is_some()) {
+                    normals.push([get(norm[0]), get(norm[1]), get(norm[2])]);
+                }
+                if uv.iter().all(|i| i.is_some()) {
+                    uvs.push([get(uv[0]), 1.0 - get(uv[1])]);
+                }
+                if col.iter().all(|i| i.is_some()) {
+                    let scale = if e.properties[col[0].unwrap()].ty == ScalarType::U8 { 255.0 } else { 1.0 };
+                    colors.push([get(col[0])/scale, get(col[1])/scale, get(col[2])/scale]);
+                }
+            } else if e.name == "face" {
+                for k in 1..list.len().saturating_sub(1) {
+                    indices.extend([list[0], list[k], list[k+1]]);
+                }
+            }
+        }
+    }
+
+    let mut indices2: Vec<u32> = vec![];
+    for t in indices.chunks_exact(3) {
+        indices2.extend([t[0], t[1], t[1], t[2], t[2], t[0]]);
+    }
+    let n = positions.len();
+    let mesh = Mesh {
+        positions,
+        normals: if normals.is_empty() { None } else { Some(normals) },
+        uvs: if uvs.is_empty() { None } else { Some(uvs) },
+        colors: if colors.is_empty() { None } else { Some(colors) },
+        tangents: None,
+        indices: Indices::auto(indices, n),
+        indices2: Indices::auto(indices2, n),
+    };
+    mesh.validate()?;
+    Ok(mesh)
+}
+
+pub fn load_ply<P: AsRef<Path>>(path: P) -> Result<Mesh> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    read_ply(BufReader::new(file)).with_context(|| format!("failed to read {}", path.display()))
+}
+// endregion: reader
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex_data::create_cube_data;
+
+    fn assert_close<const N: usize>(a:&[[f32; N]], b:&[[f32; N]], tolerance:f32) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((0..N).all(|k| (x[k] - y[k]).abs() <= tolerance), "{:?} != {:?}", x, y);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mesh = create_cube_data(2.0);
+        for format in [PlyFormat::Ascii, PlyFormat::BinaryLittleEndian, PlyFormat::BinaryBigEndian] {
+            let mut bytes: Vec<u8> = vec![];
+            write_ply(&mut bytes, &mesh, format).unwrap();
+            let read = read_ply(bytes.as_slice()).unwrap();
+            assert_close(&read.positions, &mesh.positions, 1e-6);
+            assert_close(read.normals.as_ref().unwrap(), mesh.normals.as_ref().unwrap(), 1e-6);
+            assert_close(read.uvs.as_ref().unwrap(), mesh.uvs.as_ref().unwrap(), 1e-6);
+            assert_close(read.colors.as_ref().unwrap(), mesh.colors.as_ref().unwrap(), 0.5 / 255.0);
+            assert_eq!(read.indices.to_u32(), mesh.indices.to_u32());
+        }
+    }
+
+    // the PLY and OBJ exports of a mesh store the same texture coordinates
+    #[test]
+    fn uvs_match_obj() {
+        let mesh = create_cube_data(2.0);
+        let mut ply: Vec<u8> = vec![];
+        write_ply(&mut ply, &mesh, PlyFormat::Ascii).unwrap();
+        let mut obj: Vec<u8> = vec![];
+        crate::obj::write_obj(&mut obj, &mesh).unwrap();
+
+        let ply = String::from_utf8(ply).unwrap();
+        let st: Vec<[f32; 2]> = ply.lines().skip_while(|l| *l != "end_header").skip(1)
+            .take(mesh.vertex_count())
+            .map(|l| {
+                let v: Vec<f32> = l.split_whitespace().map(|t| t.parse().unwrap()).collect();
+                [v[6], v[7]]
+            }).collect();
+        let vt: Vec<[f32; 2]> = String::from_utf8(obj).unwrap().lines()
+            .filter_map(|l| l.strip_prefix("vt "))
+            .map(|l| {
+                let v: Vec<f32> = l.split_whitespace().map(|t| t.parse().unwrap()).collect();
+                [v[0], v[1]]
+            }).collect();
+        assert_close(&st, &vt, 1e-6);
+    }
+
+    // a face claiming 2^32 - 1 vertices fails on the missing data instead of allocating
+    #[test]
+    fn oversized_list_count() {
+        let mut bytes: Vec<u8> = b"ply\nformat binary_little_endian 1.0\nelement face 1\n\
+            property list uint uint vertex_indices\nend_header\n".to_vec();
+        bytes.extend(u32::MAX.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        assert!(read_ply(bytes.as_slice()).is_err());
+    }
+}
@@ -1,81 +1,465 @@
 #![allow(dead_code)]
 use cgmath::*;
-use std::f32::consts::PI;
-use std::collections::HashMap;
 use super::colormap;
+use super::curvature::Curvature;
+use super::parallel;
 use super::math_func as mf;
+use super::vertex_data as vd;
+use super::expr::{ExprSurface, SimpleExpr};
+use std::collections::HashSet;
+use std::sync::Arc;
+use super::surface_registry::{Domain, ParametricSurface, Seam, SurfaceRegistry};
+use super::adaptive::{self, AdaptiveTessellation, ParamMesh};
+use super::mesh::{IndexFormat, Indices, Mesh};
+use super::topology::{mesh_topology, Topology};
+use super::lod::{self, Lod, LodChain};
+use anyhow::{bail, Context, Result};
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ISurfaceOutput {
     pub positions: Vec<[f32; 3]>,
     pub normals: Vec<[f32; 3]>,
+    pub tangents: Vec<[f32; 4]>,   // along increasing u (or x), w is the handedness
+    pub bitangents: Vec<[f32; 3]>, // along increasing v (or z), see tangent_frame
     pub colors: Vec<[f32; 3]>,
     pub colors2: Vec<[f32; 3]>,
     pub uvs: Vec<[f32; 2]>,
-    pub indices: Vec<u16>,
-    pub indices2: Vec<u16>,
+    pub indices: Indices,
+    pub indices2: Indices,
 } 
 
+// Attributes of one vertex, computed on their own so that vertices can be built in parallel.
+struct Vertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    tangent: [f32; 4],
+    bitangent: [f32; 3],
+    uv: [f32; 2],
+}
+
+impl ISurfaceOutput {
+    // Appends the vertices, without colors.
+    fn extend(&mut self, vertices:Vec<Vertex>) {
+        for v in vertices {
+            self.positions.push(v.position);
+            self.normals.push(v.normal);
+            self.tangents.push(v.tangent);
+            self.bitangents.push(v.bitangent);
+            self.uvs.push(v.uv);
+        }
+    }
+
+    // Merges every vertex into the vertex weld maps it to, which must come first and map to
+    // itself, keeping the attributes of the merged vertex. Triangles and lines that collapse
+    // are dropped.
+    pub fn weld(&mut self, weld:&[u32], format:IndexFormat) -> Result<()> {
+        let mut index = vec![u32::MAX; weld.len()];
+        let mut n = 0;
+        for i in 0..weld.len() {
+            if weld[i] as usize == i {
+                index[i] = n;
+                n += 1;
+            }
+        }
+        let new_index = |i:usize| index[weld[i] as usize];
+        let keep = |i:usize| weld[i] as usize == i;
+        fn compact<T:Copy>(v:&mut Vec<T>, keep:&dyn Fn(usize) -> bool) {
+            let mut i = 0;
+            v.retain(|_| { i += 1; keep(i - 1) });
+        }
+        compact(&mut self.positions, &keep);
+        compact(&mut self.normals, &keep);
+        compact(&mut self.tangents, &keep);
+        compact(&mut self.bitangents, &keep);
+        compact(&mut self.colors, &keep);
+        compact(&mut self.colors2, &keep);
+        compact(&mut self.uvs, &keep);
+
+        let mut indices: Vec<u32> = vec![];
+        for t in self.indices.to_u32().chunks(3) {
+            let t = [new_index(t[0] as usize), new_index(t[1] as usize), new_index(t[2] as usize)];
+            if t[0] != t[1] && t[1] != t[2] && t[2] != t[0] {
+                indices.extend(t);
+            }
+        }
+        let mut lines: HashSet<(u32, u32)> = HashSet::new();
+        let mut indices2: Vec<u32> = vec![];
+        for l in self.indices2.to_u32().chunks(2) {
+            let (a, b) = (new_index(l[0] as usize), new_index(l[1] as usize));
+            if a != b && lines.insert((a.min(b), a.max(b))) {
+                indices2.extend([a, b]);
+            }
+        }
+        self.indices = Indices::new(indices, n as usize, format)?;
+        self.indices2 = Indices::new(indices2, n as usize, format)?;
+        Ok(())
+    }
+}
+
+// Levels of detail of a surface whose vertices form a (nu + 1) x (nv + 1) grid, as new() of
+// the uniform surfaces returns them, by lod::decimate_grid: each level keeps every other row
+// and column of the one before, so the vertices it keeps have the same attributes as on
+// level 0. Stops after levels levels or when the grid is down to a single cell.
+fn grid_lod_chain(out:ISurfaceOutput, nu:usize, nv:usize, levels:usize, format:IndexFormat) 
+    -> Result<LodChain<ISurfaceOutput>> {
+    let mut used = vec![false; out.positions.len()];
+    for i in out.indices.iter() {
+        used[i] = true;
+    }
+    let mut chain = LodChain { levels: vec![] };
+    for k in 1..levels.max(1) {
+        let step = 1usize << k;
+        if step / 2 >= nu.max(nv) {
+            break;
+        }
+        let (kept, indices, indices2, error) = lod::decimate_grid(&out.positions, &used, nu, nv, step);
+        let pick = |v:&[[f32; 3]]| kept.iter().map(|&i| v[i]).collect();
+        let n = kept.len();
+        chain.levels.push(Lod {
+            mesh: ISurfaceOutput {
+                positions: pick(&out.positions),
+                normals: pick(&out.normals),
+                tangents: kept.iter().map(|&i| out.tangents[i]).collect(),
+                bitangents: pick(&out.bitangents),
+                colors: pick(&out.colors),
+                colors2: pick(&out.colors2),
+                uvs: kept.iter().map(|&i| out.uvs[i]).collect(),
+                indices: Indices::new(indices, n, format)?,
+                indices2: Indices::new(indices2, n, format)?,
+            },
+            error,
+        });
+    }
+    chain.levels.insert(0, Lod { mesh: out, error: 0.0 });
+    Ok(chain)
+}
+
+// the surface as a Mesh, e.g. for obj::save_obj or ply::save_ply (bitangents and colors2
+// have no Mesh channel and are dropped)
+impl From<ISurfaceOutput> for Mesh {
+    fn from(s: ISurfaceOutput) -> Self {
+        Mesh {
+            positions: s.positions,
+            normals: Some(s.normals),
+            uvs: Some(s.uvs),
+            colors: Some(s.colors),
+            tangents: Some(s.tangents),
+            indices: s.indices,
+            indices2: s.indices2,
+        }
+    }
+}
+
+// Orthonormal tangent and bitangent at a vertex with unit normal n, from the position
+// derivatives along the two texture directions. The tangent follows pu and the bitangent pv,
+// with bitangent = cross(n, t) * w as in MikkTSpace, so the tangents can go into a Mesh
+// unchanged and match Mesh::generate_tangents.
+pub(crate) fn tangent_frame(n:Vector3<f32>, pu:Vector3<f32>, pv:Vector3<f32>) -> ([f32; 4], [f32; 3]) {
+    let t = pu - n * n.dot(pu);
+    let t = if t.magnitude2() > 1e-12 { t.normalize() } else { vd::perpendicular(n) };
+    let w = if n.cross(t).dot(pv) < 0.0 { -1.0 } else { 1.0 };
+    let b = n.cross(t) * w;
+    ([t.x, t.y, t.z, w], b.into())
+}
+
+// What surface generation does with vertices whose position is not finite, as where a surface
+// takes the square root of a negative number or overflows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonFinite {
+    #[default]
+    DropTriangles,  // the vertex moves to the origin and its triangles and lines are removed
+    Clamp,          // the vertex moves to the mean of its finite neighbors
+    Error,          // generation fails, naming the first such sample
+}
+
+// Applies policy to the vertices of out whose position is not finite; the indices must be set.
+// describe names the sample of a vertex for the error. Normals and tangents that are not
+// finite at finite positions, as at the poles of many surfaces, are taken from the neighbors
+// in any case.
+fn repair_non_finite(out:&mut ISurfaceOutput, policy:NonFinite, format:IndexFormat, 
+    describe:&dyn Fn(usize) -> String) -> Result<()> {
+    let finite = |v:&[f32]| v.iter().all(|c| c.is_finite());
+    let n = out.positions.len();
+    let bad: Vec<bool> = out.positions.iter().map(|p| !finite(p)).collect();
+    let frame_bad = |out:&ISurfaceOutput, i:usize| !finite(&out.normals[i]) || !finite(&out.tangents[i]) 
+        || !finite(&out.bitangents[i]);
+    if !bad.contains(&true) && !(0..n).any(|i| frame_bad(out, i)) {
+        return Ok(());
+    }
+    if let (NonFinite::Error, Some(i)) = (policy, bad.iter().position(|&b| b)) {
+        bail!("{} is not finite", describe(i));
+    }
+
+    let triangles = out.indices.to_u32();
+    let mut neighbors: Vec<Vec<usize>> = vec![vec![]; n];
+    for t in triangles.chunks(3) {
+        for k in 0..3 {
+            let (a, b) = (t[k] as usize, t[(k + 1) % 3] as usize);
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+        }
+    }
+
+    if policy == NonFinite::Clamp {
+        // fill in from the finite side, one ring of neighbors per pass
+        let mut fixed: Vec<bool> = bad.iter().map(|&b| !b).collect();
+        while fixed.contains(&false) {
+            let mut ring: Vec<(usize, Vector3<f32>)> = vec![];
+            for i in (0..n).filter(|&i| !fixed[i]) {
+                let around: Vec<Vector3<f32>> = neighbors[i].iter().filter(|&&j| fixed[j])
+                    .map(|&j| Vector3::from(out.positions[j])).collect();
+                if !around.is_empty() {
+                    ring.push((i, around.iter().sum::<Vector3<f32>>() / around.len() as f32));
+                }
+            }
+            if ring.is_empty() {
+                let i = fixed.iter().position(|&f| !f).unwrap_or(0);
+                bail!("{} is not finite and has no finite neighbor to clamp to", describe(i));
+            }
+            for (i, p) in ring {
+                out.positions[i] = p.into();
+                out.normals[i] = [f32::NAN; 3];
+                fixed[i] = true;
+            }
+        }
+    } else {
+        for i in (0..n).filter(|&i| bad[i]) {
+            out.positions[i] = [0.0; 3];
+            out.normals[i] = [0.0, 1.0, 0.0];
+            out.tangents[i] = [1.0, 0.0, 0.0, 1.0];
+            out.bitangents[i] = [0.0, 0.0, -1.0];
+        }
+        let indices: Vec<u32> = triangles.chunks(3).filter(|t| t.iter().all(|&i| !bad[i as usize]))
+            .flatten().cloned().collect();
+        let indices2: Vec<u32> = out.indices2.to_u32().chunks(2).filter(|l| l.iter().all(|&i| !bad[i as usize]))
+            .flatten().cloned().collect();
+        out.indices = Indices::new(indices, n, format)?;
+        out.indices2 = Indices::new(indices2, n, format)?;
+    }
+
+    // frames from the finite neighbors; dropped vertices only carry placeholder frames
+    let dropped = |j:usize| bad[j] && policy != NonFinite::Clamp;
+    let broken: Vec<usize> = (0..n).filter(|&i| frame_bad(out, i)).collect();
+    for i in broken {
+        let around: Vec<usize> = neighbors[i].iter().cloned()
+            .filter(|&j| !dropped(j) && !frame_bad(out, j)).collect();
+        let normal: Vector3<f32> = around.iter().map(|&j| Vector3::from(out.normals[j])).sum();
+        let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { Vector3::unit_y() };
+        let (pu, pv) = match around.first() {
+            Some(&j) => (Vector3::new(out.tangents[j][0], out.tangents[j][1], out.tangents[j][2]), 
+                Vector3::from(out.bitangents[j])),
+            None => (Vector3::zero(), Vector3::zero()),
+        };
+        let (tangent, bitangent) = tangent_frame(normal, pu, pv);
+        out.normals[i] = normal.into();
+        out.tangents[i] = tangent;
+        out.bitangents[i] = bitangent;
+    }
+    Ok(())
+}
+
+// Scalar that picks the colormap color of each vertex.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ColorSource {
+    #[default]
+    Coordinate,         // position along colormap_direction
+    GaussianCurvature,
+    MeanCurvature,
+    MaxCurvature,       // k1, the larger principal curvature
+    MinCurvature,       // k2
+    NormalAngle,        // angle in degrees between the normal and the colormap_direction axis
+    Scalars(Vec<f32>),  // one value per vertex in vertex order, e.g. from an analysis
+}
+
+impl ColorSource {
+    fn needs_curvature(&self) -> bool {
+        matches!(self, ColorSource::GaussianCurvature | ColorSource::MeanCurvature | 
+            ColorSource::MaxCurvature | ColorSource::MinCurvature)
+    }
+
+    // Per-vertex values; curvatures must be filled when needs_curvature() is set.
+    fn values(&self, direction:u32, positions:&[[f32; 3]], normals:&[[f32; 3]], 
+        curvatures:&[Curvature]) -> Result<Vec<f32>> {
+        let d = direction as usize;
+        let curvature = |f:fn(&Curvature) -> f32| curvatures.iter().map(f).collect();
+        Ok(match self {
+            ColorSource::Coordinate => positions.iter().map(|p| p[d]).collect(),
+            ColorSource::GaussianCurvature => curvature(|c| c.gaussian),
+            ColorSource::MeanCurvature => curvature(|c| c.mean),
+            ColorSource::MaxCurvature => curvature(|c| c.k1),
+            ColorSource::MinCurvature => curvature(|c| c.k2),
+            ColorSource::NormalAngle => normals.iter().map(|n| n[d].clamp(-1.0, 1.0).acos().to_degrees()).collect(),
+            ColorSource::Scalars(values) => {
+                if values.len() != positions.len() {
+                    bail!("{} scalars for {} vertices", values.len(), positions.len());
+                }
+                values.clone()
+            }
+        })
+    }
+}
+
 // region: parametric surface
+// Partial derivatives of the surface at (u, v): exact when the surface provides them,
+// central differences with steps epsu and epsv otherwise.
+fn partials(surface:&dyn ParametricSurface, u:f32, v:f32, epsu:f32, epsv:f32) -> (Vector3<f32>, Vector3<f32>) {
+    match surface.derivatives(u, v) {
+        Some([pu, pv]) => (pu.into(), pv.into()),
+        None => {
+            let f = |u, v| Vector3::from(surface.evaluate(u, v));
+            ((f(u+epsu, v) - f(u-epsu, v)) / (2.0 * epsu), (f(u, v+epsv) - f(u, v-epsv)) / (2.0 * epsv))
+        }
+    }
+}
+
+// Curvature at (u, v) of the surface with its axes scaled by stretch, as the output positions
+// are. The second derivatives are central differences of the partials over a tenth of the 
+// grid spacing (du, dv).
+fn parametric_curvature(surface:&dyn ParametricSurface, u:f32, v:f32, du:f32, dv:f32, 
+    stretch:Vector3<f32>) -> Curvature {
+    let (h, k) = (0.1 * du, 0.1 * dv);
+    let d = |u, v| {
+        let (pu, pv) = partials(surface, u, v, 0.01 * du, 0.01 * dv);
+        (pu.mul_element_wise(stretch), pv.mul_element_wise(stretch))
+    };
+    let (pu, pv) = d(u, v);
+    let ((pu_a, _), (pu_b, _)) = (d(u + h, v), d(u - h, v));
+    let ((pu_c, pv_c), (pu_d, pv_d)) = (d(u, v + k), d(u, v - k));
+    Curvature::from_partials(pu, pv, (pu_a - pu_b) / (2.0 * h), (pu_c - pu_d) / (2.0 * k), 
+        (pv_c - pv_d) / (2.0 * k))
+}
+
+// Maps points of a parametric surface to output positions: centered on the sampled range and
+// scaled per axis.
+struct OutputMap {
+    center: Vector3<f32>,
+    stretch: Vector3<f32>,
+}
+
+impl OutputMap {
+    fn apply(&self, p:[f32; 3]) -> [f32; 3] {
+        (Vector3::from(p) - self.center).mul_element_wise(self.stretch).into()
+    }
+}
+
+// For every vertex of a parametric surface at params, the lowest index among the vertices at
+// the same point of the surface: across the seams, and along edges of the domain that
+// collapse to a single point, like the poles of a sphere. Other vertices map to themselves.
+fn seam_map(params:&[[f32; 2]], positions:&[[f32; 3]], d:Domain, seams:[Seam; 2]) -> Vec<u32> {
+    let (umin, umax, vmin, vmax) = d;
+    let mut parent: Vec<u32> = (0..params.len() as u32).collect();
+    fn root(parent:&mut [u32], i:u32) -> u32 {
+        let mut r = i;
+        while parent[r as usize] != r {
+            r = parent[r as usize];
+        }
+        parent[i as usize] = r;
+        r
+    }
+    let mut union = |a:u32, b:u32| {
+        let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+        parent[ra.max(rb) as usize] = ra.min(rb);
+    };
+
+    // params as fractions of the domain; edge[across][side] holds (t, vertex) along the edge
+    let st: Vec<[f32; 2]> = params.iter().map(|&[u, v]| [(u - umin)/(umax - umin), (v - vmin)/(vmax - vmin)]).collect();
+    let mut edges: [[Vec<(f32, u32)>; 2]; 2] = Default::default();
+    const EPS: f32 = 1e-5;
+    for (i, p) in st.iter().enumerate() {
+        for across in 0..2 {
+            let t = p[1 - across];
+            if p[across] <= EPS {
+                edges[across][0].push((t, i as u32));
+            } else if p[across] >= 1.0 - EPS {
+                edges[across][1].push((t, i as u32));
+            }
+        }
+    }
+    for across in 0..2 {
+        edges[across][0].sort_by(|a, b| a.0.total_cmp(&b.0));
+        let min_edge = &edges[across][0];
+        for &(t, i) in &edges[across][1] {
+            let Some(t) = seams[across].inverse(t) else { continue };
+            // the nearest vertex on the min edge
+            let k = min_edge.partition_point(|e| e.0 < t);
+            let nearest = [k.wrapping_sub(1), k].into_iter().filter_map(|k| min_edge.get(k))
+                .min_by(|a, b| (a.0 - t).abs().total_cmp(&(b.0 - t).abs()));
+            if let Some(&(s, j)) = nearest {
+                if (s - t).abs() <= 1e-4 {
+                    union(i, j);
+                }
+            }
+        }
+    }
+
+    // edges whose vertices all meet in one point
+    let (mut min, mut max) = (Vector3::from([f32::MAX; 3]), Vector3::from([f32::MIN; 3]));
+    for p in positions {
+        for k in 0..3 {
+            min[k] = min[k].min(p[k]);
+            max[k] = max[k].max(p[k]);
+        }
+    }
+    let tolerance = 1e-4 * (max - min).magnitude();
+    for edge in edges.iter().flatten() {
+        let Some(&(_, first)) = edge.first() else { continue };
+        let p0 = Vector3::from(positions[first as usize]);
+        if edge.iter().all(|&(_, i)| (Vector3::from(positions[i as usize]) - p0).magnitude() <= tolerance) {
+            for &(_, i) in edge {
+                union(first, i);
+            }
+        }
+    }
+    (0..params.len() as u32).map(|i| root(&mut parent, i)).collect()
+}
+
 pub struct IParametricSurface {
     pub surface_type: u32,
-    pub surface_type_map: HashMap<u32, String>,
-    pub umin: f32,
-    pub umax: f32,
-    pub vmin: f32,
-    pub vmax: f32,
+    pub registry: SurfaceRegistry,  // surface_type is an id in this registry
+    // expression surface, parsed once with expr::ExprSurface::parse, used instead of surface_type
+    pub expression: Option<ExprSurface>,
+    pub t: f32,  // animation time parameter of expression surfaces
+    // domain overrides; None uses the surface's default
+    pub umin: Option<f32>,
+    pub umax: Option<f32>,
+    pub vmin: Option<f32>,
+    pub vmax: Option<f32>,
     pub u_resolution: u16,
     pub v_resolution: u16,
     pub scale: f32,
-    pub aspect_ratio: f32,
+    pub aspect_ratio: f32,  // y scale relative to x and z
     pub colormap_name: String,
     pub wireframe_color: String,
     pub colormap_direction: u32, // 0: x-direction, 1: y-direction, 2: z-direction
+    pub color_source: ColorSource,
     pub uv_lens: [f32; 2],
-}
-
-fn surface_type_map() -> HashMap<u32,String> {
-    let mut surface_type = HashMap::new();
-    surface_type.insert(0, String::from("klein_bottle"));
-    surface_type.insert(1, String::from("astroid"));
-    surface_type.insert(2, String::from("astroid2"));
-    surface_type.insert(3, String::from("astrodal_torus"));
-    surface_type.insert(4, String::from("bohemian_dome"));
-    surface_type.insert(5, String::from("boy_shape"));
-    surface_type.insert(6, String::from("breather"));
-    surface_type.insert(7, String::from("enneper"));
-    surface_type.insert(8, String::from("figure8"));
-    surface_type.insert(9, String::from("henneberg"));
-    surface_type.insert(10, String::from("kiss"),);
-    surface_type.insert(11, String::from("klein_bottle2"));
-    surface_type.insert(12, String::from("klein_bottle3"));
-    surface_type.insert(13, String::from("kuen"));
-    surface_type.insert(14, String::from("minimal"));
-    surface_type.insert(15, String::from("parabolic_cyclide"));
-    surface_type.insert(16, String::from("pear"));
-    surface_type.insert(17, String::from("plucker_conoid"));
-    surface_type.insert(18, String::from("seashell"));
-    surface_type.insert(19, String::from("sievert_enneper"));
-    surface_type.insert(20, String::from("steiner"));
-    surface_type.insert(21, String::from("torus"));
-    surface_type.insert(22, String::from("wellenkugel"));
-    surface_type
+    pub index_format: IndexFormat,
+    pub adaptive: Option<AdaptiveTessellation>, // refine by error instead of the uniform grid
+    // Merge the vertices the seams duplicate, for geometry processing. Off, the copies stay
+    // split so that the uvs can jump, but share the normal of the first copy.
+    pub weld_seams: bool,
+    pub non_finite: NonFinite,
+    // Percentage of the samples left out at either end of the ranges that fit the surface
+    // into scale and spread the colormap, so that a few far away samples, as on the 
+    // exponential ends of minimal or henneberg, do not shrink the rest; 0 uses the full range.
+    pub range_percentile: f32,
 }
 
 pub fn get_surface_type(key:u32) -> String {
-    let map = surface_type_map();
-    map.get(&key).map(|s| s.to_string()).unwrap_or_default()
+    SurfaceRegistry::default().get(key).map(|s| s.name().to_string()).unwrap_or_default()
 }
 
 impl Default for IParametricSurface {
     fn default() -> Self {
         Self {
             surface_type: 0,
-            surface_type_map: surface_type_map(),
-            umin: -1.0,
-            umax: 1.0,
-            vmin: -1.0,
-            vmax: 1.0,
+            registry: SurfaceRegistry::default(),
+            expression: None,
+            t: 0.0,
+            umin: None,
+            umax: None,
+            vmin: None,
+            vmax: None,
             u_resolution: 80,
             v_resolution: 80,
             scale: 1.0,
@@ -83,7 +467,13 @@ impl Default for IParametricSurface {
             colormap_name: "jet".to_string(),
             wireframe_color: "white".to_string(),
             colormap_direction: 1,
+            color_source: ColorSource::Coordinate,
             uv_lens: [1.0, 1.0],
+            index_format: IndexFormat::Auto,
+            adaptive: None,
+            weld_seams: false,
+            non_finite: NonFinite::DropTriangles,
+            range_percentile: 0.0,
         }
     }
 }
@@ -98,364 +488,817 @@ impl Default for IParametricSurface {
 }*/
 
 impl IParametricSurface {
-    pub fn new(&mut self) -> ISurfaceOutput {
-        if self.surface_type == 1 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 2.0*PI, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::astroid)
-        } else if self.surface_type == 2 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 2.0*PI, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::astroid2)
-        } else if self.surface_type == 3 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (-PI, PI, 0.0, 5.0);
-            self.parametric_surface_data(&mf::astroidal_torus)
-        } else if self.surface_type == 4 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 2.0*PI, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::bohemian_dome)
-        } else if self.surface_type == 5 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, PI, 0.0, PI);
-            self.parametric_surface_data(&mf::boy_shape)
-        } else if self.surface_type == 6 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (-14.0, 14.0, -12.0*PI, 12.0*PI);
-            self.parametric_surface_data(&mf::breather)
-        } else if self.surface_type == 7 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (-3.3, 3.3, -3.3, 3.3);
-            self.parametric_surface_data(&mf::enneper)
-        } else if self.surface_type == 8 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 4.0*PI, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::figure8)
-        } else if self.surface_type == 9 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 1.0, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::henneberg)
-        } else if self.surface_type == 10 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (-0.99999, 0.99999, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::kiss)
-        } else if self.surface_type == 11 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 2.0*PI, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::klein_bottle2)
-        } else if self.surface_type == 12 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 4.0*PI, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::klein_bottle3)
-        } else if self.surface_type == 13 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (-4.5, 4.5, -5.0, 5.0);
-            self.parametric_surface_data(&mf::kuen)
-        } else if self.surface_type == 14 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (-3.0, 1.0, -3.0*PI, 3.0*PI);
-            self.parametric_surface_data(&mf::minimal)
-        } else if self.surface_type == 15 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (-5.0, 5.0, -5.0, 5.0);
-            self.parametric_surface_data(&mf::parabolic_cyclide)
-        } else if self.surface_type == 16 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 1.0, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::pear)
-        } else if self.surface_type == 17 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (-2.0, 2.0, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::plucker_conoid)
-        } else if self.surface_type == 18 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 6.0*PI, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::seashell)
-        } else if self.surface_type == 19 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (-PI/2.1, PI/2.1, 0.001, PI/1.001);
-            self.parametric_surface_data(&mf::sievert_enneper)
-        } else if self.surface_type == 20 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 1.999999*PI, 0.0, 0.999999*PI);
-            self.parametric_surface_data(&mf::steiner)
-        } else if self.surface_type == 21 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 2.0*PI, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::torus)
-        } else if self.surface_type == 22 {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, 14.5, 0.0, 5.2);
-            self.parametric_surface_data(&mf::wellenkugel)
-        } else {
-            (self.umin, self.umax, self.vmin, self.vmax) = (0.0, PI, 0.0, 2.0*PI);
-            self.parametric_surface_data(&mf::klein_bottle)
+    // Selects a surface by its registered name.
+    pub fn set_surface(&mut self, name:&str) -> Result<()> {
+        self.surface_type = self.registry.id(name)
+            .with_context(|| format!("unknown parametric surface '{}'", name))?;
+        Ok(())
+    }
+
+    fn surface(&self) -> Result<Arc<dyn ParametricSurface>> {
+        if let Some(s) = &self.expression {
+            return Ok(Arc::new(s.at_time(self.t)));
+        }
+        // unknown ids fall back to the klein bottle, the first built-in surface
+        match self.registry.get(self.surface_type).or_else(|| self.registry.get(0)) {
+            Some(s) => Ok(s),
+            None => bail!("the surface registry is empty"),
+        }
+    }
+
+    // The domain new() tessellates: the overrides, completed with the surface's defaults.
+    pub fn domain(&self) -> Result<Domain> {
+        let d = self.surface()?.domain();
+        Ok((self.umin.unwrap_or(d.0), self.umax.unwrap_or(d.1), 
+            self.vmin.unwrap_or(d.2), self.vmax.unwrap_or(d.3)))
+    }
+
+    // Levels of detail of new() by grid decimation, see grid_lod_chain; the errors are in the
+    // units of the output positions. Adaptive and welded surfaces have no grid to decimate;
+    // simplify them with Mesh::lod_chain instead.
+    pub fn lod_chain(&mut self, levels:usize) -> Result<LodChain<ISurfaceOutput>> {
+        if self.adaptive.is_some() || self.weld_seams {
+            bail!("grid levels of detail need the uniform, unwelded grid; use Mesh::lod_chain");
+        }
+        let out = self.new()?;
+        grid_lod_chain(out, self.u_resolution as usize, self.v_resolution as usize, levels, self.index_format)
+    }
+
+    // How the surface glues the edges of the domain new() tessellates.
+    pub fn seams(&self) -> Result<[Seam; 2]> {
+        Ok(self.surface()?.seams(self.domain()?))
+    }
+
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(&mut self) -> Result<ISurfaceOutput> {
+        Ok(self.surface_data()?.0)
+    }
+
+    // Connectivity of new() with the seams welded, e.g. closed and non-orientable for the
+    // klein bottle.
+    pub fn topology(&mut self) -> Result<Topology> {
+        let (out, weld) = self.surface_data()?;
+        let indices: Vec<u32> = out.indices.iter().map(|i| weld[i]).collect();
+        Ok(mesh_topology(&indices))
+    }
+
+    // For every vertex new() returns with weld_seams off, the first vertex at the same point
+    // of the surface; see weld_seams.
+    pub fn seam_map(&mut self) -> Result<Vec<u32>> {
+        let surface = self.surface()?;
+        let d = self.domain()?;
+        let (_, _, _, map) = self.parametric_surface_range(&|u, v| surface.evaluate(u, v), d);
+        let params = self.split_vertex_params(surface.as_ref(), d, &map);
+        let positions: Vec<[f32; 3]> = params.iter().map(|&[u, v]| map.apply(surface.evaluate(u, v))).collect();
+        Ok(seam_map(&params, &positions, d, surface.seams(d)))
+    }
+
+    // The output of new() with the seam map of its vertices, the identity once welded.
+    fn surface_data(&mut self) -> Result<(ISurfaceOutput, Vec<u32>)> {
+        let surface = self.surface()?;
+        let d = self.domain()?;
+        let (mut out, params) = match self.adaptive {
+            Some(options) => self.adaptive_surface_data(surface.as_ref(), d, options)?,
+            None => self.parametric_surface_data(surface.as_ref(), d)?,
+        };
+        let weld = seam_map(&params, &out.positions, d, surface.seams(d));
+        if self.weld_seams {
+            out.weld(&weld, self.index_format)?;
+            let identity = (0..out.positions.len() as u32).collect();
+            return Ok((out, identity));
         }
+        // The copies on a seam get the same normal unless the seam reverses it; their tangent
+        // and bitangent are made orthogonal to it again.
+        for (i, &w) in weld.iter().enumerate() {
+            let (n, nw) = (Vector3::from(out.normals[i]), Vector3::from(out.normals[w as usize]));
+            if w as usize != i && n.dot(nw) > 0.0 {
+                let t = out.tangents[i];
+                let (tangent, bitangent) = tangent_frame(nw, Vector3::new(t[0], t[1], t[2]), 
+                    Vector3::from(out.bitangents[i]));
+                out.normals[i] = nw.into();
+                out.tangents[i] = tangent;
+                out.bitangents[i] = bitangent;
+            }
+        }
+        Ok((out, weld))
     }
 
-    fn parametric_surface_data(&mut self, f:&dyn Fn(f32, f32) -> [f32; 3]) -> ISurfaceOutput {
-        let mut positions: Vec<[f32; 3]> = vec![];
-        let mut normals: Vec<[f32; 3]> = vec![];
-        let mut colors: Vec<[f32; 3]> = vec![];
-        let mut colors2: Vec<[f32; 3]> = vec![];
-        let mut uvs: Vec<[f32; 2]> = vec![];
+    // Curvature at every vertex of new(), in vertex order, measured on the scaled surface.
+    pub fn curvatures(&mut self) -> Result<Vec<Curvature>> {
+        let surface = self.surface()?;
+        let d = self.domain()?;
+        let (_, _, _, map) = self.parametric_surface_range(&|u, v| surface.evaluate(u, v), d);
+        let (du, dv) = self.grid_steps(d);
+        let params = self.vertex_params(surface.as_ref(), d, &map);
+        Ok(parallel::map_range(params.len(), |k| {
+            parametric_curvature(surface.as_ref(), params[k][0], params[k][1], du, dv, map.stretch)
+        }))
+    }
 
-        let du = (self.umax - self.umin)/self.u_resolution as f32;
-        let dv = (self.vmax - self.vmin)/self.v_resolution as f32;
-        let (epsu, epsv) = (0.01 * du, 0.01 * dv);
-        //let (mut p0, mut p1, mut p2, mut p3): (Vector3<f32>, Vector3<f32>, Vector3<f32>, Vector3<f32>);
+    fn grid_steps(&self, d:Domain) -> (f32, f32) {
+        ((d.1 - d.0)/self.u_resolution as f32, (d.3 - d.2)/self.v_resolution as f32)
+    }
+
+    // (u, v) of the vertices of new()
+    fn vertex_params(&self, surface:&dyn ParametricSurface, d:Domain, map:&OutputMap) -> Vec<[f32; 2]> {
+        let params = self.split_vertex_params(surface, d, map);
+        if !self.weld_seams {
+            return params;
+        }
+        let positions: Vec<[f32; 3]> = params.iter().map(|&[u, v]| map.apply(surface.evaluate(u, v))).collect();
+        let weld = seam_map(&params, &positions, d, surface.seams(d));
+        params.into_iter().enumerate().filter(|&(i, _)| weld[i] as usize == i).map(|(_, p)| p).collect()
+    }
 
-        let (min_val, max_val, pts) = self.parametric_surface_range(f);
+    // (u, v) of the vertices of new() before welding
+    fn split_vertex_params(&self, surface:&dyn ParametricSurface, d:Domain, map:&OutputMap) -> Vec<[f32; 2]> {
+        match self.adaptive {
+            Some(options) => self.adaptive_mesh(surface, d, map, options).params,
+            None => {
+                let (du, dv) = self.grid_steps(d);
+                (0..=self.u_resolution).flat_map(|i| (0..=self.v_resolution)
+                    .map(move |j| [d.0 + du * i as f32, d.2 + dv * j as f32])).collect()
+            }
+        }
+    }
+
+    // The vertex at (u, v) with output position pos.
+    fn vertex(&self, surface:&dyn ParametricSurface, d:Domain, (u, v):(f32, f32), pos:[f32; 3]) -> Vertex {
+        let (umin, umax, vmin, vmax) = d;
+        let (du, dv) = self.grid_steps(d);
+
+        let (nu, nv) = partials(surface, u, v, 0.01 * du, 0.01 * dv);
+        // positions are scaled by aspect_ratio in y, so normals are divided by it
+        let normal = nu.cross(nv);
+        let normal = Vector3::new(normal.x, normal.y / self.aspect_ratio, normal.z).normalize();
+
+        // tangent frame from the partials mapped like the positions
+        let stretch = |p:Vector3<f32>| Vector3::new(p.x, p.y * self.aspect_ratio, p.z);
+        let (tangent, bitangent) = tangent_frame(normal, stretch(nu), stretch(nv));
+
+        Vertex {
+            position: pos,
+            normal: normal.into(),
+            tangent,
+            bitangent,
+            uv: [self.uv_lens[0]*(u-umin)/(umax-umin), self.uv_lens[1]*(v-vmin)/(vmax-vmin)],
+        }
+    }
+
+    // Appends the vertices at params with output positions to out, without colors, and 
+    // returns their curvatures when the color source needs them.
+    fn push_vertices(&self, out:&mut ISurfaceOutput, surface:&dyn ParametricSurface, d:Domain, 
+        params:&[[f32; 2]], positions:&[[f32; 3]], stretch:Vector3<f32>) -> Vec<Curvature> {
+        let (du, dv) = self.grid_steps(d);
+        let with_curvature = self.color_source.needs_curvature();
+        let vertices = parallel::map_range(params.len(), |k| {
+            let [u, v] = params[k];
+            let curvature = with_curvature.then(|| parametric_curvature(surface, u, v, du, dv, stretch));
+            (self.vertex(surface, d, (u, v), positions[k]), curvature)
+        });
+        let (vertices, curvatures): (Vec<Vertex>, Vec<Option<Curvature>>) = vertices.into_iter().unzip();
+        out.extend(vertices);
+        curvatures.into_iter().flatten().collect()
+    }
+
+    // Colors from the color source; coordinates use the range of the uniform grid.
+    fn push_colors(&self, out:&mut ISurfaceOutput, (min_val, max_val):(f32, f32), 
+        curvatures:&[Curvature]) -> Result<()> {
         let cdata = colormap::colormap_data(&self.colormap_name);
         let cdata2 = colormap::colormap_data(&self.wireframe_color);
+        let values = self.color_source.values(self.colormap_direction, &out.positions, &out.normals, curvatures)?;
+        let (min_val, max_val) = match self.color_source {
+            ColorSource::Coordinate => (min_val, max_val),
+            _ => colormap::robust_range(&values, self.range_percentile),
+        };
+        let colors = parallel::map_range(values.len(), |k| {
+            let value = if values[k].is_finite() { values[k] } else { min_val };
+            (colormap::color_lerp(cdata, min_val, max_val, value), colormap::color_lerp(cdata2, min_val, max_val, value))
+        });
+        (out.colors, out.colors2) = colors.into_iter().unzip();
+        Ok(())
+    }
+
+    fn repair_non_finite(&self, out:&mut ISurfaceOutput, surface:&dyn ParametricSurface, 
+        params:&[[f32; 2]]) -> Result<()> {
+        repair_non_finite(out, self.non_finite, self.index_format, &|i| {
+            format!("{}: the sample at (u, v) = ({}, {})", surface.name(), params[i][0], params[i][1])
+        })
+    }
+
+    // The surface on the uniform grid, with the (u, v) of every vertex.
+    fn parametric_surface_data(&mut self, surface:&dyn ParametricSurface, d:Domain) 
+        -> Result<(ISurfaceOutput, Vec<[f32; 2]>)> {
+        let (umin, _, vmin, _) = d;
+        let (du, dv) = self.grid_steps(d);
+        let (min_val, max_val, pts, map) = self.parametric_surface_range(&|u, v| surface.evaluate(u, v), d);
+        let mut out = ISurfaceOutput::default();
+        let mut params: Vec<[f32; 2]> = vec![];
 
         for i in 0..=self.u_resolution {
-            let u = self.umin + du * i as f32;
+            let u = umin + du * i as f32;
             for j in 0..=self.v_resolution {
-                let v = self.vmin + dv * j as f32;                
-                positions.push(pts[i as usize][j as usize]);
-
-                // calculate normals
-                /*p0 = Vector3::from(f(u, v));
-                if u - epsu >= 0.0 {
-                    p1 = Vector3::from(f(u-epsu, v));
-                    p2 = p0 - p1;
-                } else {
-                    p1 = Vector3::from(f(u+epsu, v));
-                    p2 = p1 - p0;
-                }
-                if v - epsv >= 0.0 {
-                    p1 = Vector3::from(f(u, v-epsv));
-                    p3 = p0 - p1;
-                } else {
-                    p1 = Vector3::from(f(u, v+epsv));
-                    p3 = p1 - p0;
-                }
-                let normal = p2.cross(p3).normalize();*/
-
-                let nu = Vector3::from(f(u+epsu, v)) - Vector3::from(f(u-epsu, v));
-                let nv = Vector3::from(f(u, v+epsv)) - Vector3::from(f(u, v-epsv));
-                let normal = nu.cross(nv).normalize();
-                normals.push(normal.into());
-
-                // colormap
-                let color = colormap::color_lerp(cdata, min_val, max_val, 
-                    pts[i as usize][j as usize][self.colormap_direction as usize]);
-                let color2 = colormap::color_lerp(cdata2, min_val, max_val, 
-                    pts[i as usize][j as usize][self.colormap_direction as usize]);
-                colors.push(color);
-                colors2.push(color2);
-
-                // uvs
-                uvs.push([self.uv_lens[0]*(u-self.umin)/(self.umax-self.umin), 
-                    self.uv_lens[1]*(v-self.vmin)/(self.vmax-self.vmin)
-                ]);
+                let v = vmin + dv * j as f32;                
+                params.push([u, v]);
             }
         }
+        let positions: Vec<[f32; 3]> = pts.into_iter().flatten().collect();
+        let curvatures = self.push_vertices(&mut out, surface, d, &params, &positions, map.stretch);
 
         // calculate indices
-        let mut indices: Vec<u16> = vec![];
-        let mut indices2: Vec<u16> = vec![];
-        let vertices_per_row = self.v_resolution + 1;
+        let mut indices: Vec<u32> = vec![];
+        let mut indices2: Vec<u32> = vec![];
+        let vertices_per_row = self.v_resolution as u32 + 1;
 
-        for i in 0..self.u_resolution {
-            for j in 0..self.v_resolution {
+        for i in 0..self.u_resolution as u32 {
+            for j in 0..self.v_resolution as u32 {
                 let idx0 = j + i * vertices_per_row;
                 let idx1 = j + 1 + i * vertices_per_row;
                 let idx2 = j + 1 + (i + 1) * vertices_per_row;
                 let idx3 = j + (i + 1) * vertices_per_row; 
 
-                let values:Vec<u16> = vec![idx0, idx1, idx2, idx2, idx3, idx0];
+                let values:Vec<u32> = vec![idx0, idx1, idx2, idx2, idx3, idx0];
                 indices.extend(values);
 
-                let values2:Vec<u16> = vec![idx0, idx1, idx0, idx3];
+                let values2:Vec<u32> = vec![idx0, idx1, idx0, idx3];
                 indices2.extend(values2);
-                if i == self.u_resolution - 1 || j == self.v_resolution - 1 {
-                    let edge_values:Vec<u16> = vec![idx1, idx2, idx2, idx3];
+                if i == self.u_resolution as u32 - 1 || j == self.v_resolution as u32 - 1 {
+                    let edge_values:Vec<u32> = vec![idx1, idx2, idx2, idx3];
                     indices2.extend(edge_values);
                 }
             }
         }
 
-        ISurfaceOutput { positions, normals, colors, colors2, uvs, indices, indices2 }
+        let n = out.positions.len();
+        out.indices = Indices::new(indices, n, self.index_format)?;
+        out.indices2 = Indices::new(indices2, n, self.index_format)?;
+        self.repair_non_finite(&mut out, surface, &params)?;
+        self.push_colors(&mut out, (min_val, max_val), &curvatures)?;
+        Ok((out, params))
     }
 
-    fn parametric_surface_range(&mut self, f:&dyn Fn(f32, f32) -> [f32; 3]) -> (f32, f32, Vec<Vec<[f32;3]>>) {
-        let du = (self.umax - self.umin)/self.u_resolution as f32;
-        let dv = (self.vmax - self.vmin)/self.v_resolution as f32;
-        let (mut xmin, mut ymin, mut zmin) = (f32::MAX, f32::MAX, f32::MAX);
-        let (mut xmax, mut ymax, mut zmax) = (f32::MIN, f32::MIN, f32::MIN);       
+    fn adaptive_mesh(&self, surface:&dyn ParametricSurface, d:Domain, map:&OutputMap, 
+        options:AdaptiveTessellation) -> ParamMesh {
+        let (du, dv) = self.grid_steps(d);
+        let point = |u, v| Vector3::from(map.apply(surface.evaluate(u, v)));
+        let normal = |u, v| {
+            let (nu, nv) = partials(surface, u, v, 0.01 * du, 0.01 * dv);
+            nu.mul_element_wise(map.stretch).cross(nv.mul_element_wise(map.stretch)).normalize()
+        };
+        adaptive::tessellate(options, d, surface.seams(d), &point, &normal)
+    }
 
-        let mut pts: Vec<Vec<[f32; 3]>> = vec![];
-        for i in 0..=self.u_resolution {
-            let u = self.umin + du * i as f32;
-            let mut pt1: Vec<[f32; 3]> = vec![];
-            for j in 0..=self.v_resolution {
-                let v = self.vmin + dv * j as f32;
-                let pt = f(u, v);
-                xmin = if pt[0] < xmin { pt[0] } else { xmin };
-                xmax = if pt[0] > xmax { pt[0] } else { xmax };
-                ymin = if pt[1] < ymin { pt[1] } else { ymin };
-                ymax = if pt[1] > ymax { pt[1] } else { ymax };
-                zmin = if pt[2] < zmin { pt[2] } else { zmin };
-                zmax = if pt[2] > zmax { pt[2] } else { zmax };
-                pt1.push(pt);
-            }
-            pts.push(pt1);
-        }
+    // Like parametric_surface_data, with the vertices placed by adaptive::tessellate. The 
+    // uniform grid still fixes the scaling and the coordinate color range, so both give the
+    // same positions and colors for a given surface.
+    fn adaptive_surface_data(&mut self, surface:&dyn ParametricSurface, d:Domain, 
+        options:AdaptiveTessellation) -> Result<(ISurfaceOutput, Vec<[f32; 2]>)> {
+        let (min_val, max_val, _, map) = self.parametric_surface_range(&|u, v| surface.evaluate(u, v), d);
+        let mesh = self.adaptive_mesh(surface, d, &map, options);
+        let mut out = ISurfaceOutput::default();
+        let positions = parallel::map_range(mesh.params.len(), |k| {
+            map.apply(surface.evaluate(mesh.params[k][0], mesh.params[k][1]))
+        });
+        let curvatures = self.push_vertices(&mut out, surface, d, &mesh.params, &positions, map.stretch);
+        let n = out.positions.len();
+        out.indices = Indices::new(mesh.indices, n, self.index_format)?;
+        out.indices2 = Indices::new(mesh.indices2, n, self.index_format)?;
+        self.repair_non_finite(&mut out, surface, &mesh.params)?;
+        self.push_colors(&mut out, (min_val, max_val), &curvatures)?;
+        Ok((out, mesh.params))
+    }
+
+    // Scaled positions on the grid with the range of the colormap coordinate, and the map
+    // that scales them.
+    fn parametric_surface_range(&mut self, f:&(dyn Fn(f32, f32) -> [f32; 3] + Sync), d:Domain) 
+        -> (f32, f32, Vec<Vec<[f32;3]>>, OutputMap) {
+        let (umin, umax, vmin, vmax) = d;
+        let du = (umax - umin)/self.u_resolution as f32;
+        let dv = (vmax - vmin)/self.v_resolution as f32;
+
+        let mut pts: Vec<Vec<[f32; 3]>> = parallel::map_range(self.u_resolution as usize + 1, |i| {
+            let u = umin + du * i as f32;
+            (0..=self.v_resolution).map(|j| f(u, vmin + dv * j as f32)).collect()
+        });
 
-        let (mut min_val, mut max_val) = (f32::MAX, f32::MIN);
+        // ranges of the finite samples; non-finite ones are left to repair_non_finite
+        let axis = |k:usize| {
+            let values: Vec<f32> = pts.iter().flatten().map(|p| p[k]).collect();
+            colormap::percentile_bounds(&values, self.range_percentile).unwrap_or((0.0, 0.0))
+        };
+        let ((xmin, xmax), (ymin, ymax), (zmin, zmax)) = (axis(0), axis(1), axis(2));
         let dist = (xmax - xmin).max(ymax - ymin).max(zmax - zmin);
+        let dist = if dist > 0.0 { dist } else { 1.0 };
+        let map = OutputMap {
+            center: Vector3::new(xmin + xmax, ymin + ymax, zmin + zmax) * 0.5,
+            stretch: Vector3::new(1.0, self.aspect_ratio, 1.0) * (self.scale / dist),
+        };
 
-        for i in 0..=self.u_resolution {
-            for j in 0..=self.v_resolution {
-                let mut pt = pts[i as usize][j as usize];
-                pt[0] = self.scale * (pt[0] - 0.5 * (xmin + xmax)) / dist;
-                pt[1] = self.scale * (pt[1] - 0.5 * (ymin + ymax)) / dist;
-                pt[2] = self.scale * (pt[2] - 0.5 * (zmin + zmax)) / dist;
-                let pt1 = pt[self.colormap_direction as usize];
-                min_val = if pt1 < min_val { pt1 } else { min_val };
-                max_val = if pt1 > max_val { pt1 } else { max_val };
-                pts[i as usize][j as usize] = pt;
-            }
+        for p in pts.iter_mut().flatten() {
+            *p = map.apply(*p);
         }
-        (min_val, max_val, pts)
+        let values: Vec<f32> = pts.iter().flatten().map(|p| p[self.colormap_direction as usize]).collect();
+        let (min_val, max_val) = colormap::robust_range(&values, self.range_percentile);
+        (min_val, max_val, pts, map)
     }
 }
 // endregion: parametric surface
 
 // region: simple surface
+type SimpleFn = fn(f32, f32, f32) -> [f32; 3];
+type BoxedSimpleFn = Box<dyn Fn(f32, f32, f32) -> [f32; 3] + Send + Sync>;
+type SimpleSampler = dyn Fn(f32, f32, f32) -> [f32; 3] + Sync;
+
+// resolved domain and aspect ratio of one ISimpleSurface::new call, with the range of the
+// function values used to normalize the positions
+struct Grid {
+    domain: Domain,
+    aspect_ratio: f32,
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
 pub struct ISimpleSurface {
     pub surface_type: u32,
-    pub xmin: f32,
-    pub xmax: f32,
-    pub zmin: f32,
-    pub zmax: f32,
+    pub expression: Option<String>, // expr::SimpleExpr source, used instead of surface_type
+    // domain overrides; None uses the surface's default
+    pub xmin: Option<f32>,
+    pub xmax: Option<f32>,
+    pub zmin: Option<f32>,
+    pub zmax: Option<f32>,
     pub x_resolution: u16,
     pub z_resolution: u16,
     pub scale: f32,
-    pub aspect_ratio: f32,
+    pub aspect_ratio: Option<f32>,  // y scale relative to x and z; None uses the surface's default
     pub colormap_name: String,
     pub wireframe_color: String,
     pub colormap_direction: u32, // 0: x-direction, 1: y-direction, 2: z-direction
+    pub color_source: ColorSource,
     pub t: f32,  // animation time parameter
     pub uv_lens: [f32; 2],
+    pub index_format: IndexFormat,
+    pub non_finite: NonFinite,
+    pub range_percentile: f32,  // see IParametricSurface::range_percentile
 }
 
 impl Default for ISimpleSurface {
     fn default() -> Self {
         Self {
             surface_type: 0,
-            xmin: -1.0,
-            xmax: 1.0,
-            zmin: -1.0,
-            zmax: 1.0,
+            expression: None,
+            xmin: None,
+            xmax: None,
+            zmin: None,
+            zmax: None,
             x_resolution: 30,
             z_resolution: 30,
             scale: 1.0,
-            aspect_ratio: 1.0,
+            aspect_ratio: None,
             colormap_name: "jet".to_string(),
             wireframe_color: "white".to_string(),
             colormap_direction: 1,
+            color_source: ColorSource::Coordinate,
             t: 0.0,
             uv_lens: [1.0, 1.0],
+            index_format: IndexFormat::Auto,
+            non_finite: NonFinite::DropTriangles,
+            range_percentile: 0.0,
         }
     }
 }
 
 impl ISimpleSurface { 
-    pub fn new(&mut self) -> ISurfaceOutput {
+    // function, default domain and default aspect ratio of the built-in surface_type
+    fn builtin(&self) -> (SimpleFn, Domain, f32) {
         if self.surface_type == 0 {
-            (self.xmin, self.xmax, self.zmin, self.zmax) = (-8.0, 8.0, -8.0, 8.0);
-            self.aspect_ratio = 0.5;
-            self.simple_surface_data(&mf::sinc)
+            (mf::sinc, (-8.0, 8.0, -8.0, 8.0), 0.5)
         } else if self.surface_type == 1 {
-            (self.xmin, self.xmax, self.zmin, self.zmax) = (-8.0, 8.0, -8.0, 8.0);
-            self.aspect_ratio = 0.6;
-            self.simple_surface_data(&mf::poles)
+            (mf::poles, (-8.0, 8.0, -8.0, 8.0), 0.6)
         } else {
-            (self.xmin, self.xmax, self.zmin, self.zmax) = (-3.0, 3.0, -3.0, 3.0);
-            self.aspect_ratio = 0.9;
-            self.simple_surface_data(&mf::peaks)  
-        }
-    }
-
-    fn simple_surface_data(&mut self, f:&dyn Fn(f32, f32, f32) -> [f32; 3]) -> ISurfaceOutput {
-        let mut positions: Vec<[f32; 3]> = vec![];
-        let mut normals: Vec<[f32; 3]> = vec![];
-        let mut colors: Vec<[f32; 3]> = vec![];
-        let mut colors2: Vec<[f32; 3]> = vec![];
-        let mut uvs: Vec<[f32; 2]> = vec![];
-        
-        let dx = (self.xmax- self.xmin) / self.x_resolution as f32;
-        let dz = (self.zmax - self.zmin) / self.z_resolution as f32;
-        let (epsx, epsz) = (0.01 * dx, 0.01 * dz);
-        
-        let (ymin, ymax) = self.yrange(f);
-        let cdata = colormap::colormap_data(&self.colormap_name);
-        let cdata2 = colormap::colormap_data(&self.wireframe_color);
+            (mf::peaks, (-3.0, 3.0, -3.0, 3.0), 0.9)
+        }
+    }
 
-        for i in 0..=self.x_resolution {
-            let x = self.xmin + dx * i as f32;
-            for j in 0..=self.z_resolution {
-                let z = self.zmin + dz * j as f32;
-                let pos = self.normalize_data(f(x,z,self.t), ymin, ymax);
-                positions.push(pos);
-
-                // calculate normals
-                let nx = Vector3::from(self.normalize_data(f(x+epsx, z, self.t), ymin, ymax)) - 
-                         Vector3::from(self.normalize_data(f(x-epsx, z, self.t), ymin, ymax));
-                let nz = Vector3::from(self.normalize_data(f(x, z+epsz, self.t), ymin, ymax)) - 
-                         Vector3::from(self.normalize_data(f(x, z-epsz, self.t), ymin, ymax));
-                let normal = nx.cross(nz).normalize();
-                normals.push(normal.into());
-
-                // colormap
-                let range = if self.colormap_direction == 1 { self.scale * self.aspect_ratio} 
-                    else {self.scale};
-                let color = colormap::color_lerp(cdata, -range, range, 
-                    pos[self.colormap_direction as usize]);
-                let color2 = colormap::color_lerp(cdata2, -range, range, 
-                    pos[self.colormap_direction as usize]);
-                colors.push(color);
-                colors2.push(color2);
-
-                // uvs
-                uvs.push([self.uv_lens[0]*(x-self.xmin)/(self.xmax-self.xmin), 
-                    self.uv_lens[1]*(z-self.zmin)/(self.zmax-self.zmin)
-                ]);
+    // default domain and aspect ratio of the expression, or else of the built-in surface
+    fn defaults(&self) -> (Domain, f32) {
+        match self.expression {
+            Some(_) => (SimpleExpr::DOMAIN, SimpleExpr::ASPECT_RATIO),
+            None => {
+                let (_, d, a) = self.builtin();
+                (d, a)
             }
         }
+    }
 
-        // calculate indices
-        let mut indices: Vec<u16> = vec![];
-        let mut indices2: Vec<u16> = vec![];
-        let vertices_per_row = self.z_resolution + 1;
+    // The (xmin, xmax, zmin, zmax) domain new() tessellates.
+    pub fn domain(&self) -> Domain {
+        let (d, _) = self.defaults();
+        (self.xmin.unwrap_or(d.0), self.xmax.unwrap_or(d.1), 
+            self.zmin.unwrap_or(d.2), self.zmax.unwrap_or(d.3))
+    }
+
+    pub fn effective_aspect_ratio(&self) -> f32 {
+        self.aspect_ratio.unwrap_or(self.defaults().1)
+    }
+
+    fn function(&self) -> Result<BoxedSimpleFn> {
+        Ok(match &self.expression {
+            Some(source) => {
+                let e = SimpleExpr::parse(source)?;
+                Box::new(move |x, z, t| e.eval(x, z, t))
+            }
+            None => Box::new(self.builtin().0),
+        })
+    }
+
+    fn grid(&mut self, f:&SimpleSampler) -> Grid {
+        let mut grid = Grid { 
+            domain: self.domain(), 
+            aspect_ratio: self.effective_aspect_ratio(), 
+            min: [0.0; 3], 
+            max: [0.0; 3],
+        };
+        (grid.min, grid.max) = self.data_range(f, &grid);
+        grid
+    }
+
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(&mut self) -> Result<ISurfaceOutput> {
+        let f = self.function()?;
+        let grid = self.grid(&f);
+        let mut out = ISurfaceOutput::default();
+        (out.indices, out.indices2) = self.grid_indices()?;
+        self.simple_surface_data(&f, &grid, &mut out)?;
+        Ok(out)
+    }
+
+    // Levels of detail of new() by grid decimation, see grid_lod_chain.
+    pub fn lod_chain(&mut self, levels:usize) -> Result<LodChain<ISurfaceOutput>> {
+        let out = self.new()?;
+        grid_lod_chain(out, self.x_resolution as usize, self.z_resolution as usize, levels, self.index_format)
+    }
+
+    // Curvature at every vertex of new(), in vertex order, measured on the scaled surface.
+    pub fn curvatures(&mut self) -> Result<Vec<Curvature>> {
+        let f = self.function()?;
+        let grid = self.grid(&f);
+        let (xmin, xmax, zmin, zmax) = grid.domain;
+        let dx = (xmax - xmin) / self.x_resolution as f32;
+        let dz = (zmax - zmin) / self.z_resolution as f32;
+        let res = self.z_resolution as usize + 1;
+        Ok(parallel::map_range((self.x_resolution as usize + 1) * res, |k| {
+            self.curvature_at(&f, &grid, xmin + dx * (k / res) as f32, zmin + dz * (k % res) as f32)
+        }))
+    }
+
+    // Curvature of the scaled surface at (x, z), from central differences over a tenth of the
+    // grid spacing.
+    fn curvature_at(&self, f:&SimpleSampler, g:&Grid, x:f32, z:f32) -> Curvature {
+        let (xmin, xmax, zmin, zmax) = g.domain;
+        let h = 0.1 * (xmax - xmin) / self.x_resolution as f32;
+        let k = 0.1 * (zmax - zmin) / self.z_resolution as f32;
+        let p = |x, z| Vector3::from(self.normalize_data(f(x, z, self.t), g));
+        let p0 = p(x, z);
+        let (px0, px1, pz0, pz1) = (p(x - h, z), p(x + h, z), p(x, z - k), p(x, z + k));
+        let pxz = (p(x + h, z + k) - p(x + h, z - k) - p(x - h, z + k) + p(x - h, z - k)) / (4.0 * h * k);
+        Curvature::from_partials((px1 - px0) / (2.0 * h), (pz1 - pz0) / (2.0 * k), 
+            (px1 - p0 * 2.0 + px0) / (h * h), pxz, (pz1 - p0 * 2.0 + pz0) / (k * k))
+    }
+
+    // Normal, tangent and bitangent of the scaled surface at (x, z), from central differences
+    // over a hundredth of the grid spacing; new() and update() share it so that their frames
+    // agree.
+    fn frame_at(&self, f:&SimpleSampler, g:&Grid, x:f32, z:f32) -> ([f32; 3], [f32; 4], [f32; 3]) {
+        let (xmin, xmax, zmin, zmax) = g.domain;
+        let epsx = 0.01 * (xmax - xmin) / self.x_resolution as f32;
+        let epsz = 0.01 * (zmax - zmin) / self.z_resolution as f32;
+        let p = |x, z| Vector3::from(self.normalize_data(f(x, z, self.t), g));
+        let pu = p(x + epsx, z) - p(x - epsx, z);
+        let pv = p(x, z + epsz) - p(x, z - epsz);
+        let normal = pu.cross(pv).normalize();
+        let (tangent, bitangent) = tangent_frame(normal, pu, pv);
+        (normal.into(), tangent, bitangent)
+    }
 
-        for i in 0..self.x_resolution {
-            for j in 0..self.z_resolution {
+    // Recomputes the vertex attributes that change with t (positions, normals, tangents, 
+    // bitangents and colors) in place, keeping the uvs of out, which must come from new() with
+    // the same resolution. Use this for animation instead of calling new() every frame: the
+    // range is measured in the pass that evaluates the positions, the frames are computed as in
+    // new() and the buffers are refilled without reallocating. The indices are only rebuilt
+    // after a frame that dropped triangles.
+    pub fn update(&mut self, out:&mut ISurfaceOutput) -> Result<()> {
+        let (nx, nz) = (self.x_resolution as usize, self.z_resolution as usize);
+        let n = (nx + 1) * (nz + 1);
+        if out.positions.len() != n || out.uvs.len() != n {
+            bail!("surface output has {} vertices but the grid has {}; call new() after \
+                changing the resolution", out.positions.len(), n);
+        }
+        let f = self.function()?;
+        let mut grid = Grid { 
+            domain: self.domain(), 
+            aspect_ratio: self.effective_aspect_ratio(), 
+            min: [f32::MAX; 3], 
+            max: [f32::MIN; 3],
+        };
+        let (xmin, xmax, zmin, zmax) = grid.domain;
+        let dx = (xmax - xmin) / nx as f32;
+        let dz = (zmax - zmin) / nz as f32;
+        let res = nz + 1;
+
+        for (k, p) in out.positions.iter_mut().enumerate() {
+            *p = f(xmin + dx * (k / res) as f32, zmin + dz * (k % res) as f32, self.t);
+            for (c, &value) in p.iter().enumerate() {
+                if value.is_finite() {
+                    grid.min[c] = grid.min[c].min(value);
+                    grid.max[c] = grid.max[c].max(value);
+                }
+            }
+        }
+        // the same range as data_range gives new()
+        for c in 0..3 {
+            if self.range_percentile > 0.0 {
+                let values: Vec<f32> = out.positions.iter().map(|p| p[c]).collect();
+                (grid.min[c], grid.max[c]) = colormap::percentile_bounds(&values, self.range_percentile)
+                    .unwrap_or((-1.0, 1.0));
+            } else if grid.min[c] > grid.max[c] {
+                (grid.min[c], grid.max[c]) = (-1.0, 1.0);
+            }
+        }
+        for p in out.positions.iter_mut() {
+            *p = self.normalize_data(*p, &grid);
+        }
+
+        let frames = parallel::map_range(n, |k| {
+            self.frame_at(&f, &grid, xmin + dx * (k / res) as f32, zmin + dz * (k % res) as f32)
+        });
+        out.normals.resize(n, [0.0; 3]);
+        out.tangents.resize(n, [0.0; 4]);
+        out.bitangents.resize(n, [0.0; 3]);
+        for (k, (normal, tangent, bitangent)) in frames.into_iter().enumerate() {
+            out.normals[k] = normal;
+            out.tangents[k] = tangent;
+            out.bitangents[k] = bitangent;
+        }
+
+        if out.indices.len() < 6 * nx * nz {
+            (out.indices, out.indices2) = self.grid_indices()?;
+        }
+        repair_non_finite(out, self.non_finite, self.index_format, &|i| {
+            let (x, z) = (xmin + dx * (i / res) as f32, zmin + dz * (i % res) as f32);
+            format!("simple surface: the sample at (x, z) = ({}, {})", x, z)
+        })?;
+
+        let curvatures: Vec<Curvature> = if self.color_source.needs_curvature() {
+            parallel::map_range(n, |k| {
+                self.curvature_at(&f, &grid, xmin + dx * (k / res) as f32, zmin + dz * (k % res) as f32)
+            })
+        } else {
+            vec![]
+        };
+        self.simple_colors(out, &grid, &curvatures)
+    }
+
+    // Triangle and wireframe indices of the grid; they only depend on the resolution.
+    pub fn grid_indices(&self) -> Result<(Indices, Indices)> {
+        let mut indices: Vec<u32> = vec![];
+        let mut indices2: Vec<u32> = vec![];
+        let vertices_per_row = self.z_resolution as u32 + 1;
+
+        for i in 0..self.x_resolution as u32 {
+            for j in 0..self.z_resolution as u32 {
                 let idx0 = j + i * vertices_per_row;
                 let idx1 = j + 1 + i * vertices_per_row;
                 let idx2 = j + 1 + (i + 1) * vertices_per_row;
                 let idx3 = j + (i + 1) * vertices_per_row; 
 
-                let values:Vec<u16> = vec![idx0, idx1, idx2, idx2, idx3, idx0];
+                let values:Vec<u32> = vec![idx0, idx1, idx2, idx2, idx3, idx0];
                 indices.extend(values);
 
-                let values2:Vec<u16> = vec![idx0, idx1, idx0, idx3];
+                let values2:Vec<u32> = vec![idx0, idx1, idx0, idx3];
                 indices2.extend(values2);
-                if i == self.x_resolution - 1 || j == self.z_resolution - 1 {
-                    let edge_values:Vec<u16> = vec![idx1, idx2, idx2, idx3];
+                if i == self.x_resolution as u32 - 1 || j == self.z_resolution as u32 - 1 {
+                    let edge_values:Vec<u32> = vec![idx1, idx2, idx2, idx3];
                     indices2.extend(edge_values);
                 }
             }
         }
 
-        ISurfaceOutput { positions, normals, colors, colors2, uvs, indices, indices2 }
+        let n = (self.x_resolution as usize + 1) * (self.z_resolution as usize + 1);
+        Ok((Indices::new(indices, n, self.index_format)?, Indices::new(indices2, n, self.index_format)?))
+    }
+
+    // Fills the vertex attributes of out, reusing its buffers. The indices must be set already;
+    // non-finite samples are handled by policy.
+    fn simple_surface_data(&mut self, f:&SimpleSampler, g:&Grid, out:&mut ISurfaceOutput) -> Result<()> {
+        let (xmin, xmax, zmin, zmax) = g.domain;
+        let dx = (xmax - xmin) / self.x_resolution as f32;
+        let dz = (zmax - zmin) / self.z_resolution as f32;
+        let with_curvature = self.color_source.needs_curvature();
+        let res = self.z_resolution as usize + 1;
+
+        let vertices = parallel::map_range((self.x_resolution as usize + 1) * res, |k| {
+            let x = xmin + dx * (k / res) as f32;
+            let z = zmin + dz * (k % res) as f32;
+            let pos = self.normalize_data(f(x,z,self.t), g);
+            let (normal, tangent, bitangent) = self.frame_at(f, g, x, z);
+            let curvature = with_curvature.then(|| self.curvature_at(f, g, x, z));
+
+            let uv = [self.uv_lens[0]*(x-xmin)/(xmax-xmin), self.uv_lens[1]*(z-zmin)/(zmax-zmin)];
+            (Vertex { position: pos, normal, tangent, bitangent, uv }, curvature)
+        });
+        let (vertices, curvatures): (Vec<Vertex>, Vec<Option<Curvature>>) = vertices.into_iter().unzip();
+        let curvatures: Vec<Curvature> = curvatures.into_iter().flatten().collect();
+        out.extend(vertices);
+
+        repair_non_finite(out, self.non_finite, self.index_format, &|i| {
+            let (x, z) = (xmin + dx * (i / res) as f32, zmin + dz * (i % res) as f32);
+            format!("simple surface: the sample at (x, z) = ({}, {})", x, z)
+        })?;
+
+        self.simple_colors(out, g, &curvatures)
+    }
+
+    // Colors of the vertices of out; coordinates use the fixed range of the normalized
+    // positions, which keeps the colors steady during animation.
+    fn simple_colors(&self, out:&mut ISurfaceOutput, g:&Grid, curvatures:&[Curvature]) -> Result<()> {
+        let cdata = colormap::colormap_data(&self.colormap_name);
+        let cdata2 = colormap::colormap_data(&self.wireframe_color);
+        out.colors.clear();
+        out.colors2.clear();
+        if let ColorSource::Coordinate = self.color_source {
+            let range = if self.colormap_direction == 1 { self.scale * g.aspect_ratio}
+                else {self.scale};
+            let d = self.colormap_direction as usize;
+            let positions = &out.positions;
+            let colors = parallel::map_range(positions.len(), |k| {
+                (colormap::color_lerp(cdata, -range, range, positions[k][d]), 
+                    colormap::color_lerp(cdata2, -range, range, positions[k][d]))
+            });
+            for (color, color2) in colors {
+                out.colors.push(color);
+                out.colors2.push(color2);
+            }
+        } else {
+            let values = self.color_source.values(self.colormap_direction, &out.positions, 
+                &out.normals, curvatures)?;
+            let (min_val, max_val) = colormap::robust_range(&values, self.range_percentile);
+            let colors = parallel::map_range(values.len(), |k| {
+                let value = if values[k].is_finite() { values[k] } else { min_val };
+                (colormap::color_lerp(cdata, min_val, max_val, value), colormap::color_lerp(cdata2, min_val, max_val, value))
+            });
+            for (color, color2) in colors {
+                out.colors.push(color);
+                out.colors2.push(color2);
+            }
+        }
+        Ok(())
+    }
+
+    fn normalize_data(&self, point:[f32; 3], g:&Grid) -> [f32; 3] {
+        // a flat coordinate, e.g. the height of "y = 0", goes to the middle of the range
+        let t = |k:usize| if g.max[k] > g.min[k] { (point[k] - g.min[k]) / (g.max[k] - g.min[k]) } else { 0.5 };
+        [
+            (-1.0 + 2.0 * t(0)) * self.scale,
+            (-1.0 + 2.0 * t(1)) * self.scale * g.aspect_ratio,
+            (-1.0 + 2.0 * t(2)) * self.scale,
+        ]
+    }
+
+    // Range of the finite function values over the domain, see range_percentile. x and z are
+    // measured as well because a function may not return its inputs unchanged (peaks swaps
+    // them).
+    fn data_range(&mut self, f:&SimpleSampler, g:&Grid) -> ([f32; 3], [f32; 3]) {
+        let (xmin, xmax, zmin, zmax) = g.domain;
+        let dx = (xmax - xmin) / self.x_resolution as f32;
+        let dz = (zmax - zmin) / self.z_resolution as f32;
+        let res = self.z_resolution as usize + 1;
+        let pts = parallel::map_range((self.x_resolution as usize + 1) * res, |k| {
+            f(xmin + dx * (k / res) as f32, zmin + dz * (k % res) as f32, self.t)
+        });
+        let mut min = [0.0; 3];
+        let mut max = [0.0; 3];
+        for k in 0..3 {
+            let values: Vec<f32> = pts.iter().map(|p| p[k]).collect();
+            (min[k], max[k]) = colormap::percentile_bounds(&values, self.range_percentile).unwrap_or((-1.0, 1.0));
+        }
+        (min, max)
+    }
+}
+// endregion: simple surface
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin(name:&str) -> IParametricSurface {
+        let surface_type = SurfaceRegistry::default().id(name).unwrap();
+        IParametricSurface { surface_type, ..Default::default() }
+    }
+
+    // kiss takes the square root of 1 - u, so it is not finite beyond u = 1
+    fn kiss_beyond_tip(non_finite:NonFinite) -> IParametricSurface {
+        IParametricSurface { umin: Some(-1.0), umax: Some(1.5), u_resolution: 25, v_resolution: 12, 
+            non_finite, ..builtin("kiss") }
+    }
+
+    fn all_finite(out:&ISurfaceOutput) -> bool {
+        let finite = |v:&[f32]| v.iter().all(|c| c.is_finite());
+        out.positions.iter().all(|p| finite(p)) && out.normals.iter().all(|n| finite(n))
+            && out.tangents.iter().all(|t| finite(t)) && out.bitangents.iter().all(|b| finite(b))
+    }
+
+    #[test]
+    fn non_finite_drop_triangles() {
+        let mut s = kiss_beyond_tip(NonFinite::DropTriangles);
+        let out = s.new().unwrap();
+        assert!(all_finite(&out));
+        // rows 0..=20 reach u = 1, the four rows after them are not finite
+        let row = |i:usize| i / 13;
+        assert!(out.indices.iter().all(|i| row(i) <= 20));
+        assert!(out.indices2.iter().all(|i| row(i) <= 20));
+        assert_eq!(out.indices.len(), 6 * 20 * 12);
+        assert!((21 * 13..out.positions.len()).all(|i| out.positions[i] == [0.0; 3]));
+    }
+
+    #[test]
+    fn non_finite_clamp() {
+        let mut s = kiss_beyond_tip(NonFinite::Clamp);
+        let out = s.new().unwrap();
+        assert!(all_finite(&out));
+        assert_eq!(out.indices.len(), 6 * 25 * 12);
+    }
+
+    #[test]
+    fn non_finite_error() {
+        let mut s = kiss_beyond_tip(NonFinite::Error);
+        let e = s.new().err().unwrap().to_string();
+        assert!(e.starts_with("kiss: the sample at (u, v) = (1.1") && e.ends_with("is not finite"), "{}", e);
     }
 
-    fn normalize_data(&mut self, point:[f32; 3], ymin:f32, ymax:f32) -> [f32; 3] {
-        let mut pt = point.clone();
-        pt[0] = (-1.0 + 2.0 * (pt[0] - self.xmin) / (self.xmax - self.xmin)) * self.scale;
-        pt[1] = (-1.0 + 2.0 * (pt[1] - ymin) / (ymax - ymin)) * self.scale * self.aspect_ratio;
-        pt[2] = (-1.0 + 2.0 * (pt[2] - self.zmin) / (self.zmax - self.zmin)) * self.scale;
-        pt
+    // A vertex whose frame is broken takes it from its finite neighbors, never from the
+    // placeholder frame of a dropped vertex.
+    #[test]
+    fn frames_ignore_dropped_vertices() {
+        // a 3 x 3 grid laid out like ISimpleSurface's, on a plane tilted away from the
+        // placeholder normal
+        let mut out = ISurfaceOutput::default();
+        for i in 0..3 {
+            for j in 0..3 {
+                out.positions.push([0.8 * i as f32, -0.6 * i as f32, j as f32]);
+                out.normals.push([0.6, 0.8, 0.0]);
+                out.tangents.push([0.8, -0.6, 0.0, 1.0]);
+                out.bitangents.push([0.0, 0.0, -1.0]);
+            }
+        }
+        let s = ISimpleSurface { x_resolution: 2, z_resolution: 2, ..Default::default() };
+        (out.indices, out.indices2) = s.grid_indices().unwrap();
+        out.positions[4] = [f32::NAN; 3];
+        out.normals[1] = [f32::NAN; 3];
+        repair_non_finite(&mut out, NonFinite::DropTriangles, IndexFormat::U32, &|i| i.to_string()).unwrap();
+        let n = Vector3::from(out.normals[1]);
+        assert!((n - Vector3::new(0.6, 0.8, 0.0)).magnitude() < 1e-6, "{:?}", n);
     }
 
-    fn yrange(&mut self, f:&dyn Fn(f32, f32, f32) -> [f32; 3]) -> (f32, f32) {
-        let dx = (self.xmax- self.xmin) / self.x_resolution as f32;
-        let dz = (self.zmax - self.zmin) / self.z_resolution as f32;
-        let mut ymin = f32::MAX;
-        let mut ymax = f32::MIN;
-    
-        for i in 0..=self.x_resolution {
-            let x = self.xmin + dx * i as f32;
-            for j in 0..=self.z_resolution {
-                let z = self.zmin + dz * j as f32;
-                let pt = f(x, z, self.t);
-                ymin = if pt[1] < ymin { pt[1] } else { ymin };
-                ymax = if pt[1] > ymax { pt[1] } else { ymax };
+    #[test]
+    fn closed_surfaces() {
+        for weld_seams in [false, true] {
+            let torus = IParametricSurface { weld_seams, ..builtin("torus") }.topology().unwrap();
+            assert!(torus.closed && torus.orientable, "{:?}", torus);
+            assert_eq!((torus.euler_characteristic, torus.components), (0, 1));
+            let klein = IParametricSurface { weld_seams, ..builtin("klein_bottle") }.topology().unwrap();
+            assert!(klein.closed && !klein.orientable, "{:?}", klein);
+            assert_eq!((klein.euler_characteristic, klein.components), (0, 1));
+        }
+    }
+
+    // refinement keeps the cells on both sides of a seam matched, so the closed surfaces stay
+    // closed, and stops at the vertex budget
+    #[test]
+    fn adaptive_closed_surfaces() {
+        for name in ["torus", "klein_bottle"] {
+            for max_vertices in [500, 3000, 20000] {
+                let adaptive = AdaptiveTessellation { max_vertices, ..Default::default() };
+                let mut s = IParametricSurface { adaptive: Some(adaptive), ..builtin(name) };
+                let topology = s.topology().unwrap();
+                assert_eq!(topology.boundary_edges, 0, "{} with {} vertices", name, max_vertices);
+                assert_eq!(topology.non_manifold_edges, 0, "{} with {} vertices", name, max_vertices);
+                let out = s.new().unwrap();
+                assert!(out.positions.len() <= max_vertices, "{}: {} vertices", name, out.positions.len());
             }
         }
-        (ymin, ymax)
+    }
+
+    // update() refills the buffers of new() with what new() would return at the new time
+    #[test]
+    fn simple_update_matches_new() {
+        let mut s = ISimpleSurface { x_resolution: 20, z_resolution: 24, t: 0.5, ..Default::default() };
+        let mut out = s.new().unwrap();
+        s.t = 1.5;
+        s.update(&mut out).unwrap();
+        let fresh = s.new().unwrap();
+        assert_eq!(out.positions, fresh.positions);
+        assert_eq!(out.normals, fresh.normals);
+        assert_eq!(out.tangents, fresh.tangents);
+        assert_eq!(out.bitangents, fresh.bitangents);
+        assert_eq!(out.colors, fresh.colors);
     }
 }
-// endregion: simple surface
\ No newline at end of file
@@ -0,0 +1,284 @@
+#![allow(dead_code)]
+use std::f32::consts::PI;
+use std::sync::Arc;
+use cgmath::*;
+use super::dual::Dual;
+use super::math_func as mf;
+use anyhow::{bail, Result};
+
+// (umin, umax, vmin, vmax) of a parametric surface
+pub type Domain = (f32, f32, f32, f32);
+
+type SurfaceFn = fn(f32, f32) -> [f32; 3];
+type DualSurfaceFn = fn(Dual, Dual) -> [Dual; 3];
+// How a parametric surface glues the opposite edges of its domain. For the u edges t is v as a
+// fraction of its range, and the other way around for the v edges: P(umin, t) meets 
+// P(umax, offset + t) on a periodic seam and P(umax, offset - t) on a twisted one, as on the
+// klein bottle, wrapping around modulo 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Seam {
+    Open,
+    Periodic(f32),  // offset
+    Twisted(f32),   // offset
+}
+
+impl Seam {
+    // The t on the max edge that meets t on the min edge; None for open seams.
+    pub fn map(self, t:f32) -> Option<f32> {
+        let s = match self {
+            Seam::Open => return None,
+            Seam::Periodic(offset) => offset + t,
+            Seam::Twisted(offset) => offset - t,
+        };
+        Some(if (0.0..=1.0).contains(&s) { s } else { s.rem_euclid(1.0) })
+    }
+
+    // The t on the min edge that meets t on the max edge.
+    pub fn inverse(self, t:f32) -> Option<f32> {
+        match self {
+            Seam::Periodic(offset) => Seam::Periodic(-offset).map(t),
+            _ => self.map(t),
+        }
+    }
+
+    pub fn is_open(self) -> bool {
+        self == Seam::Open
+    }
+}
+
+// Finds the seams of f over the domain by comparing points along opposite edges, trying the 
+// offsets that are multiples of 1/16. Points count as equal within 1e-4 of the size of the
+// surface. Meant for surfaces that cannot declare their seams, such as expr::ExprSurface.
+pub fn detect_seams(f:&dyn Fn(f32, f32) -> [f32; 3], d:Domain) -> [Seam; 2] {
+    let (umin, umax, vmin, vmax) = d;
+    const N: usize = 16;
+    let (mut min, mut max) = (Vector3::from([f32::MAX; 3]), Vector3::from([f32::MIN; 3]));
+    for i in 0..=N {
+        for j in 0..=N {
+            let (a, b) = (i as f32 / N as f32, j as f32 / N as f32);
+            let p = Vector3::from(f(umin + (umax - umin) * a, vmin + (vmax - vmin) * b));
+            for k in 0..3 {
+                min[k] = min[k].min(p[k]);
+                max[k] = max[k].max(p[k]);
+            }
+        }
+    }
+    let tolerance = 1e-4 * (max - min).magnitude();
+    let seam = |across:usize| {
+        // the point at t on the min (s = 0) or max (s = 1) edge
+        let p = |s:f32, t:f32| Vector3::from(if across == 0 {
+            f(umin + (umax - umin) * s, vmin + (vmax - vmin) * t)
+        } else {
+            f(umin + (umax - umin) * t, vmin + (vmax - vmin) * s)
+        });
+        let glued = |seam:Seam| (0..=2 * N).all(|k| {
+            let t = k as f32 / (2 * N) as f32;
+            seam.map(t).is_some_and(|m| (p(0.0, t) - p(1.0, m)).magnitude() <= tolerance)
+        });
+        (0..N).map(|k| Seam::Periodic(k as f32 / N as f32))
+            .chain((0..=N).map(|k| Seam::Twisted(k as f32 / N as f32)))
+            .find(|&seam| glued(seam)).unwrap_or(Seam::Open)
+    };
+    [seam(0), seam(1)]
+}
+
+type DerivativesFn = Box<dyn Fn(f32, f32) -> [[f32; 3]; 2] + Send + Sync>;
+
+// A parametric surface that IParametricSurface can tessellate.
+pub trait ParametricSurface: Send + Sync {
+    fn name(&self) -> &str;
+    fn evaluate(&self, u:f32, v:f32) -> [f32; 3];
+    // the domain that shows the whole surface
+    fn domain(&self) -> Domain;
+    // Partial derivatives [dP/du, dP/dv]. Surfaces returning None are differentiated 
+    // numerically.
+    fn derivatives(&self, _u:f32, _v:f32) -> Option<[[f32; 3]; 2]> {
+        None
+    }
+    // How the surface glues the edges of the domain d; open unless the surface declares
+    // its seams.
+    fn seams(&self, _d:Domain) -> [Seam; 2] {
+        [Seam::Open; 2]
+    }
+}
+
+// Surface defined by a function or closure, used for the built-in math_func surfaces.
+pub struct FnSurface {
+    name: String,
+    domain: Domain,
+    f: Box<dyn Fn(f32, f32) -> [f32; 3] + Send + Sync>,
+    derivatives: Option<DerivativesFn>,
+    seams: Option<[Seam; 2]>,
+}
+
+impl FnSurface {
+    pub fn new<F>(name:&str, domain:Domain, f:F) -> Self
+    where F: Fn(f32, f32) -> [f32; 3] + Send + Sync + 'static {
+        Self { name: name.to_string(), domain, f: Box::new(f), derivatives: None, seams: None }
+    }
+
+    // analytic partial derivatives, returned as [dP/du, dP/dv]
+    pub fn with_derivatives<D>(mut self, d:D) -> Self
+    where D: Fn(f32, f32) -> [[f32; 3]; 2] + Send + Sync + 'static {
+        self.derivatives = Some(Box::new(d));
+        self
+    }
+
+    // Seams of the surface over its own domain; other domains are open.
+    pub fn with_seams(mut self, seams:[Seam; 2]) -> Self {
+        self.seams = Some(seams);
+        self
+    }
+
+    // partial derivatives from the same surface written for dual numbers, e.g. the 
+    // functions in math_func::dual
+    pub fn with_dual<D>(self, df:D) -> Self
+    where D: Fn(Dual, Dual) -> [Dual; 3] + Send + Sync + 'static {
+        self.with_derivatives(move |u, v| {
+            let p = df(Dual::u(u), Dual::v(v));
+            [p.map(|c| c.du), p.map(|c| c.dv)]
+        })
+    }
+}
+
+impl ParametricSurface for FnSurface {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn evaluate(&self, u:f32, v:f32) -> [f32; 3] {
+        (self.f)(u, v)
+    }
+
+    fn domain(&self) -> Domain {
+        self.domain
+    }
+
+    fn derivatives(&self, u:f32, v:f32) -> Option<[[f32; 3]; 2]> {
+        self.derivatives.as_ref().map(|d| d(u, v))
+    }
+
+    fn seams(&self, d:Domain) -> [Seam; 2] {
+        match self.seams {
+            Some(seams) if d == self.domain => seams,
+            _ => [Seam::Open; 2],
+        }
+    }
+}
+
+// Surfaces by id and name. The id is the registration order, so the built-in surfaces keep
+// the surface_type numbers they have always had and registered surfaces follow them.
+#[derive(Clone)]
+pub struct SurfaceRegistry {
+    surfaces: Vec<Arc<dyn ParametricSurface>>,
+}
+
+impl Default for SurfaceRegistry {
+    fn default() -> Self {
+        Self::with_builtins()
+    }
+}
+
+impl SurfaceRegistry {
+    pub fn empty() -> Self {
+        Self { surfaces: vec![] }
+    }
+
+    pub fn with_builtins() -> Self {
+        // how each surface glues the edges of its domain, see Seam
+        let (open, periodic, twisted) = (Seam::Open, Seam::Periodic(0.0), Seam::Twisted(0.5));
+        let builtins: [(&str, Domain, [Seam; 2], SurfaceFn, DualSurfaceFn); 23] = [
+            ("klein_bottle", (0.0, PI, 0.0, 2.0*PI), [twisted, periodic], mf::klein_bottle, mf::dual::klein_bottle),
+            ("astroid", (0.0, 2.0*PI, 0.0, 2.0*PI), [periodic, periodic], mf::astroid, mf::dual::astroid),
+            ("astroid2", (0.0, 2.0*PI, 0.0, 2.0*PI), [periodic, periodic], mf::astroid2, mf::dual::astroid2),
+            ("astrodal_torus", (-PI, PI, 0.0, 5.0), [periodic, open], mf::astroidal_torus, mf::dual::astroidal_torus),
+            ("bohemian_dome", (0.0, 2.0*PI, 0.0, 2.0*PI), [periodic, periodic], mf::bohemian_dome, mf::dual::bohemian_dome),
+            ("boy_shape", (0.0, PI, 0.0, PI), [periodic, Seam::Twisted(0.0)], mf::boy_shape, mf::dual::boy_shape),
+            ("breather", (-14.0, 14.0, -12.0*PI, 12.0*PI), [open, open], mf::breather, mf::dual::breather),
+            ("enneper", (-3.3, 3.3, -3.3, 3.3), [open, open], mf::enneper, mf::dual::enneper),
+            ("figure8", (0.0, 4.0*PI, 0.0, 2.0*PI), [periodic, periodic], mf::figure8, mf::dual::figure8),
+            ("henneberg", (0.0, 1.0, 0.0, 2.0*PI), [open, periodic], mf::henneberg, mf::dual::henneberg),
+            ("kiss", (-0.99999, 0.99999, 0.0, 2.0*PI), [open, periodic], mf::kiss, mf::dual::kiss),
+            ("klein_bottle2", (0.0, 2.0*PI, 0.0, 2.0*PI), [twisted, periodic], mf::klein_bottle2, mf::dual::klein_bottle2),
+            ("klein_bottle3", (0.0, 4.0*PI, 0.0, 2.0*PI), [periodic, periodic], mf::klein_bottle3, mf::dual::klein_bottle3),
+            ("kuen", (-4.5, 4.5, -5.0, 5.0), [open, open], mf::kuen, mf::dual::kuen),
+            ("minimal", (-3.0, 1.0, -3.0*PI, 3.0*PI), [open, open], mf::minimal, mf::dual::minimal),
+            ("parabolic_cyclide", (-5.0, 5.0, -5.0, 5.0), [open, open], mf::parabolic_cyclide, mf::dual::parabolic_cyclide),
+            ("pear", (0.0, 1.0, 0.0, 2.0*PI), [open, periodic], mf::pear, mf::dual::pear),
+            ("plucker_conoid", (-2.0, 2.0, 0.0, 2.0*PI), [open, periodic], mf::plucker_conoid, mf::dual::plucker_conoid),
+            ("seashell", (0.0, 6.0*PI, 0.0, 2.0*PI), [open, periodic], mf::seashell, mf::dual::seashell),
+            ("sievert_enneper", (-PI/2.1, PI/2.1, 0.001, PI/1.001), [open, open], mf::sievert_enneper, mf::dual::sievert_enneper),
+            ("steiner", (0.0, 1.999999*PI, 0.0, 0.999999*PI), [periodic, periodic], mf::steiner, mf::dual::steiner),
+            ("torus", (0.0, 2.0*PI, 0.0, 2.0*PI), [periodic, periodic], mf::torus, mf::dual::torus),
+            ("wellenkugel", (0.0, 14.5, 0.0, 5.2), [open, open], mf::wellenkugel, mf::dual::wellenkugel),
+        ];
+        let mut registry = Self::empty();
+        for (name, domain, seams, f, df) in builtins {
+            registry.surfaces.push(Arc::new(FnSurface::new(name, domain, f).with_dual(df).with_seams(seams)));
+        }
+        registry
+    }
+
+    // Adds a surface and returns its id. Names must be unique.
+    pub fn register<S: ParametricSurface + 'static>(&mut self, surface:S) -> Result<u32> {
+        if self.id(surface.name()).is_some() {
+            bail!("a surface named '{}' is already registered", surface.name());
+        }
+        self.surfaces.push(Arc::new(surface));
+        Ok(self.surfaces.len() as u32 - 1)
+    }
+
+    pub fn register_fn<F>(&mut self, name:&str, domain:Domain, f:F) -> Result<u32>
+    where F: Fn(f32, f32) -> [f32; 3] + Send + Sync + 'static {
+        self.register(FnSurface::new(name, domain, f))
+    }
+
+    pub fn get(&self, id:u32) -> Option<Arc<dyn ParametricSurface>> {
+        self.surfaces.get(id as usize).cloned()
+    }
+
+    pub fn get_by_name(&self, name:&str) -> Option<Arc<dyn ParametricSurface>> {
+        self.id(name).and_then(|id| self.get(id))
+    }
+
+    pub fn id(&self, name:&str) -> Option<u32> {
+        self.surfaces.iter().position(|s| s.name() == name).map(|i| i as u32)
+    }
+
+    pub fn len(&self) -> usize {
+        self.surfaces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.surfaces.is_empty()
+    }
+
+    // (id, surface) pairs in id order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &dyn ParametricSurface)> + '_ {
+        self.surfaces.iter().enumerate().map(|(i, s)| (i as u32, s.as_ref()))
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.surfaces.iter().map(|s| s.name()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the declared seams of the built-in surfaces are the ones the surfaces really have
+    #[test]
+    fn builtin_seams() {
+        for (_, s) in SurfaceRegistry::default().iter() {
+            let d = s.domain();
+            assert_eq!(s.seams(d), detect_seams(&|u, v| s.evaluate(u, v), d), "{}", s.name());
+        }
+    }
+
+    #[test]
+    fn other_domains_are_open() {
+        let torus = SurfaceRegistry::default().get_by_name("torus").unwrap();
+        assert_eq!(torus.seams((0.0, PI, 0.0, 2.0 * PI)), [Seam::Open; 2]);
+    }
+}
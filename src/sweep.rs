@@ -0,0 +1,349 @@
+#![allow(dead_code)]
+use cgmath::*;
+use super::mesh::Mesh;
+use super::surface_data::tangent_frame;
+use super::vertex_data::{self as vd, LatheRing, MeshBuilder};
+use anyhow::{bail, Result};
+
+// 2D cross section for the sweep, revolution and extrusion generators. The surface faces to
+// the right of the direction the points run in, so a closed counterclockwise profile faces
+// outward. Smooth profiles share vertices and average the normals at the points; otherwise
+// every side gets its own vertices and a flat normal.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub points: Vec<[f32; 2]>,
+    pub closed: bool,
+    pub smooth: bool,
+}
+
+// A profile vertex: position, unit normal and arc length fraction s along the profile.
+#[derive(Debug, Clone, Copy)]
+struct ProfileVertex {
+    p: Vector2<f32>,
+    normal: Vector2<f32>,
+    s: f32,
+}
+
+fn signed_area(points:&[[f32; 2]]) -> f32 {
+    let n = points.len();
+    (0..n).map(|i| {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        a[0] * b[1] - b[0] * a[1]
+    }).sum::<f32>() * 0.5
+}
+
+impl Profile {
+    pub fn circle(r:f32, n:u16) -> Self {
+        let n = n.max(3);
+        let points = (0..n).map(|i| {
+            let a = Rad(i as f32 * 2.0 * std::f32::consts::PI / n as f32);
+            [r * a.cos(), r * a.sin()]
+        }).collect();
+        Self { points, closed: true, smooth: true }
+    }
+
+    // Closed polygon with flat sides, reordered counterclockwise if needed.
+    pub fn polygon(points:&[[f32; 2]]) -> Self {
+        let mut points = points.to_vec();
+        if signed_area(&points) < 0.0 {
+            points.reverse();
+        }
+        Self { points, closed: true, smooth: false }
+    }
+
+    pub fn open(points:&[[f32; 2]], smooth:bool) -> Self {
+        Self { points: points.to_vec(), closed: false, smooth }
+    }
+
+    // Runs of vertices that are connected in order: one run for a smooth profile (closed ones
+    // repeat the first point with s = 1 for the uv seam) and one per side otherwise.
+    fn strips(&self) -> Result<Vec<Vec<ProfileVertex>>> {
+        let mut points: Vec<Vector2<f32>> = self.points.iter().map(|&p| p.into()).collect();
+        if points.len() < 2 || (self.closed && points.len() < 3) {
+            bail!("a profile needs at least {} points", if self.closed { 3 } else { 2 });
+        }
+        if self.closed {
+            points.push(points[0]);
+        }
+        let mut lengths: Vec<f32> = vec![];
+        let mut normals: Vec<Vector2<f32>> = vec![];
+        for w in points.windows(2) {
+            let d = w[1] - w[0];
+            let l = d.magnitude();
+            if l == 0.0 {
+                bail!("the profile repeats the point {:?}", [w[0].x, w[0].y]);
+            }
+            lengths.push(l);
+            normals.push(Vector2::new(d.y, -d.x) / l);
+        }
+        let total:f32 = lengths.iter().sum();
+        let mut s = vec![0.0];
+        for l in &lengths {
+            s.push(s[s.len() - 1] + l / total);
+        }
+        let m = normals.len();
+
+        if !self.smooth {
+            return Ok((0..m).map(|i| vec![
+                ProfileVertex { p: points[i], normal: normals[i], s: s[i] },
+                ProfileVertex { p: points[i + 1], normal: normals[i], s: s[i + 1] },
+            ]).collect());
+        }
+        let strip = (0..points.len()).map(|i| {
+            let before = if i > 0 { i - 1 } else if self.closed { m - 1 } else { 0 };
+            let after = if i < m { i } else if self.closed { 0 } else { m - 1 };
+            let sum = normals[before] + normals[after];
+            let normal = if sum.magnitude2() > 1e-12 { sum.normalize() } else { normals[after] };
+            ProfileVertex { p: points[i], normal, s: s[i] }
+        }).collect();
+        Ok(vec![strip])
+    }
+}
+
+// Surface of revolution around the y axis from a profile of (radius, y) points. A profile
+// running upward on the outside, or a closed counterclockwise one, is seen from outside.
+// Points with radius 0 become poles. u follows the angle like the cylinder walls and v the
+// arc length of the profile, from its end (v = 0) to its start (v = 1).
+pub fn create_revolution_data(profile:&Profile, n:u16, uv_lens:[f32; 2]) -> Result<Mesh> {
+    if profile.points.iter().any(|p| p[0] < 0.0) {
+        bail!("the profile of a surface of revolution needs radii >= 0");
+    }
+    let n = n.max(3) as u32;
+    let mut b = MeshBuilder::default();
+    for strip in profile.strips()? {
+        // lathe expects the surface to the left of the rings, so they run backward
+        let rings: Vec<LatheRing> = strip.iter().rev().map(|pv| LatheRing {
+            rho: pv.p.x,
+            y: pv.p.y,
+            normal: pv.normal.into(),
+            v: 1.0 - pv.s,
+        }).collect();
+        vd::lathe(&mut b, &rings, n);
+    }
+    for uv in &mut b.uvs {
+        *uv = [uv[0] * uv_lens[0], uv[1] * uv_lens[1]];
+    }
+    Ok(b.build())
+}
+
+// Point of a sweep path with its frame; the profile x and y axes map to n and b = t x n, and
+// v is the arc length fraction along the path.
+struct Frame {
+    origin: Vector3<f32>,
+    t: Vector3<f32>,
+    n: Vector3<f32>,
+    b: Vector3<f32>,
+    v: f32,
+}
+
+// Rotation minimizing frames at n + 1 samples of the curve, from the double reflection method
+// (Wang et al., 2008). Unlike Frenet frames they do not flip at inflection points or spin
+// around straight parts. On a closed curve the twist left between the last and the first
+// frame is spread evenly along the path so the ends line up.
+fn path_frames(curve:&dyn Fn(f32) -> [f32; 3], t0:f32, t1:f32, closed:bool, n:u32) -> Result<Vec<Frame>> {
+    let dt = (t1 - t0) / n as f32;
+    let eps = 0.01 * dt;
+    let mut frames: Vec<Frame> = vec![];
+    for i in 0..=n {
+        let t = t0 + dt * i as f32;
+        let origin = Vector3::from(curve(t));
+        let d = Vector3::from(curve(t + eps)) - Vector3::from(curve(t - eps));
+        if d.magnitude2() == 0.0 || !d.magnitude2().is_finite() {
+            bail!("the sweep path has no direction at t = {}", t);
+        }
+        frames.push(Frame { origin, t: d.normalize(), n: Vector3::zero(), b: Vector3::zero(), v: 0.0 });
+    }
+
+    frames[0].n = vd::perpendicular(frames[0].t);
+    let mut length = 0.0;
+    for i in 0..n as usize {
+        let (x0, t0, r0) = (frames[i].origin, frames[i].t, frames[i].n);
+        let (x1, t1) = (frames[i + 1].origin, frames[i + 1].t);
+        let v1 = x1 - x0;
+        let c1 = v1.magnitude2();
+        let (r_l, t_l) = if c1 > 0.0 {
+            (r0 - v1 * (2.0 / c1 * v1.dot(r0)), t0 - v1 * (2.0 / c1 * v1.dot(t0)))
+        } else {
+            (r0, t0)
+        };
+        let v2 = t1 - t_l;
+        let c2 = v2.magnitude2();
+        let r1 = if c2 > 0.0 { r_l - v2 * (2.0 / c2 * v2.dot(r_l)) } else { r_l };
+        // remove the drift out of the normal plane
+        frames[i + 1].n = (r1 - t1 * r1.dot(t1)).normalize();
+        length += v1.magnitude();
+        frames[i + 1].v = length;
+    }
+    if length == 0.0 {
+        bail!("the sweep path has zero length");
+    }
+
+    let last = n as usize;
+    let angle = if closed {
+        let (nl, tl, nf) = (frames[last].n, frames[last].t, frames[0].n);
+        tl.dot(nl.cross(nf)).atan2(nl.dot(nf))
+    } else {
+        0.0
+    };
+    for f in &mut frames {
+        f.v /= length;
+        let a = angle * f.v;
+        let b = f.t.cross(f.n);
+        f.n = f.n * a.cos() + b * a.sin();
+        f.b = f.t.cross(f.n);
+    }
+    Ok(frames)
+}
+
+// Places the profile strips at every frame and joins consecutive frames.
+fn sweep(b:&mut MeshBuilder, strips:&[Vec<ProfileVertex>], frames:&[Frame], uv_lens:[f32; 2]) {
+    for strip in strips {
+        let start = b.positions.len() as u32;
+        let m = strip.len() as u32;
+        for f in frames {
+            for pv in strip {
+                let pos = f.origin + f.n * pv.p.x + f.b * pv.p.y;
+                let normal = f.n * pv.normal.x + f.b * pv.normal.y;
+                let du = f.n * -pv.normal.y + f.b * pv.normal.x;
+                let (tangent, _) = tangent_frame(normal, du, f.t);
+                b.push(pos.into(), normal.into(), [uv_lens[0] * pv.s, uv_lens[1] * f.v], tangent);
+            }
+        }
+        let rows = frames.len() as u32;
+        for j in 0..rows {
+            for i in 0..m {
+                let idx0 = start + j * m + i;
+                if i + 1 < m {
+                    b.indices2.extend([idx0, idx0 + 1]);
+                }
+                if j + 1 < rows {
+                    b.indices2.extend([idx0, idx0 + m]);
+                    if i + 1 < m {
+                        let (idx1, idx2, idx3) = (idx0 + 1, idx0 + m, idx0 + m + 1);
+                        b.indices.extend([idx0, idx1, idx3, idx3, idx2, idx0]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Ear clipping triangulation of a simple polygon; the triangles are counterclockwise.
+fn triangulate(points:&[[f32; 2]]) -> Result<Vec<u32>> {
+    let p = |i:usize| Vector2::from(points[i]);
+    let cross = |a:Vector2<f32>, b:Vector2<f32>, c:Vector2<f32>| (b - a).perp_dot(c - a);
+    let n = points.len();
+    for i in 0..n {
+        for j in i + 2..n {
+            if (j + 1) % n == i {
+                continue;
+            }
+            let (a, b, c, d) = (p(i), p((i + 1) % n), p(j), p((j + 1) % n));
+            let (c1, c2) = (cross(a, b, c), cross(a, b, d));
+            let (c3, c4) = (cross(c, d, a), cross(c, d, b));
+            if c1 * c2 <= 0.0 && c3 * c4 <= 0.0 && (c1, c2, c3, c4) != (0.0, 0.0, 0.0, 0.0) {
+                bail!("the profile polygon intersects itself");
+            }
+        }
+    }
+    let mut remaining: Vec<usize> = (0..n).collect();
+    if signed_area(points) < 0.0 {
+        remaining.reverse();
+    }
+    let mut triangles: Vec<u32> = vec![];
+    while remaining.len() > 3 {
+        let k = remaining.len();
+        let corner = |i:usize| [remaining[(i + k - 1) % k], remaining[i], remaining[(i + 1) % k]];
+        let ear = (0..k).find(|&i| {
+            let [ia, ib, ic] = corner(i);
+            let (a, b, c) = (p(ia), p(ib), p(ic));
+            cross(a, b, c) > 0.0 && remaining.iter().all(|&j| {
+                let q = p(j);
+                j == ia || j == ib || j == ic
+                    || cross(a, b, q) < 0.0 || cross(b, c, q) < 0.0 || cross(c, a, q) < 0.0
+            })
+        });
+        match ear {
+            Some(i) => {
+                triangles.extend(corner(i).map(|j| j as u32));
+                remaining.remove(i);
+            }
+            None => bail!("the profile polygon intersects itself"),
+        }
+    }
+    triangles.extend(remaining.iter().map(|&j| j as u32));
+    Ok(triangles)
+}
+
+// Flat caps at the first and last frame, facing away from the path, with planar uvs over the
+// bounding square of the profile.
+fn caps(b:&mut MeshBuilder, profile:&Profile, first:&Frame, last:&Frame) -> Result<()> {
+    let triangles = triangulate(&profile.points)?;
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for p in &profile.points {
+        for k in 0..2 {
+            min[k] = min[k].min(p[k]);
+            max[k] = max[k].max(p[k]);
+        }
+    }
+    let size = (max[0] - min[0]).max(max[1] - min[1]);
+    for (f, end) in [(first, false), (last, true)] {
+        let normal = if end { f.t } else { -f.t };
+        let (tangent, _) = tangent_frame(normal, f.n, -f.b);
+        let start = b.positions.len() as u32;
+        for p in &profile.points {
+            let pos = f.origin + f.n * p[0] + f.b * p[1];
+            let uv = [(p[0] - min[0]) / size, (max[1] - p[1]) / size];
+            b.push(pos.into(), normal.into(), uv, tangent);
+        }
+        // counterclockwise in the profile plane means facing +t
+        for tri in triangles.chunks(3) {
+            if end {
+                b.indices.extend([start + tri[0], start + tri[1], start + tri[2]]);
+            } else {
+                b.indices.extend([start + tri[2], start + tri[1], start + tri[0]]);
+            }
+        }
+        let m = profile.points.len() as u32;
+        for i in 0..m {
+            b.indices2.extend([start + i, start + (i + 1) % m]);
+        }
+    }
+    Ok(())
+}
+
+// Sweeps a profile along the curve c(t), t0 <= t <= t1, sampled at n + 1 points. The profile
+// lies in the plane normal to the curve and keeps its orientation along rotation minimizing
+// frames, so tubes do not twist. Open curves get flat caps when the profile is closed; a
+// closed curve must return to its start at t1. u follows the arc length of the profile and
+// v the arc length of the path, both scaled by uv_lens.
+pub fn create_sweep_data<F>(curve:F, t0:f32, t1:f32, closed:bool, n:u16, profile:&Profile, uv_lens:[f32; 2]) -> Result<Mesh>
+where F: Fn(f32) -> [f32; 3] {
+    let frames = path_frames(&curve, t0, t1, closed, n.max(1) as u32)?;
+    let mut b = MeshBuilder::default();
+    sweep(&mut b, &profile.strips()?, &frames, uv_lens);
+    if profile.closed && !closed {
+        caps(&mut b, profile, &frames[0], &frames[frames.len() - 1])?;
+    }
+    Ok(b.build())
+}
+
+// Prism from a profile in the xz plane, given as (x, -z) so that counterclockwise is seen
+// counterclockwise from above, extruded from y = -h/2 to h/2. Closed profiles get flat caps.
+// v runs up the sides from 0 to 1.
+pub fn create_extrusion_data(profile:&Profile, h:f32, uv_lens:[f32; 2]) -> Result<Mesh> {
+    let frame = |y:f32, v:f32| Frame {
+        origin: Vector3::new(0.0, y, 0.0),
+        t: Vector3::unit_y(),
+        n: Vector3::unit_x(),
+        b: -Vector3::unit_z(),
+        v,
+    };
+    let frames = [frame(-h/2.0, 0.0), frame(h/2.0, 1.0)];
+    let mut b = MeshBuilder::default();
+    sweep(&mut b, &profile.strips()?, &frames, uv_lens);
+    if profile.closed {
+        caps(&mut b, profile, &frames[0], &frames[1])?;
+    }
+    Ok(b.build())
+}
@@ -0,0 +1,150 @@
+#![allow(dead_code)]
+use std::collections::{HashMap, VecDeque};
+
+// Connectivity of a triangle mesh. Only vertices used by a triangle are counted, and
+// triangles repeating a vertex are skipped. An edge of one triangle is a boundary edge, an
+// edge of more than two is non-manifold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Topology {
+    pub vertices: usize,
+    pub edges: usize,
+    pub faces: usize,
+    pub boundary_edges: usize,
+    pub non_manifold_edges: usize,
+    pub components: usize,
+    pub closed: bool,      // no boundary edges
+    pub orientable: bool,  // the triangles can be wound consistently
+    pub euler_characteristic: i64,  // V - E + F
+}
+
+impl Topology {
+    // Genus of a closed, orientable and connected manifold surface, e.g. 0 for a sphere and
+    // 1 for a torus.
+    pub fn genus(&self) -> Option<i64> {
+        let manifold = self.closed && self.orientable && self.non_manifold_edges == 0;
+        if manifold && self.components == 1 {
+            Some((2 - self.euler_characteristic) / 2)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn mesh_topology(indices:&[u32]) -> Topology {
+    let faces: Vec<[u32; 3]> = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]])
+        .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0]).collect();
+
+    // the faces along every edge, with whether they run the edge from its lower vertex
+    let mut edges: HashMap<(u32, u32), Vec<(usize, bool)>> = HashMap::new();
+    let mut vertices: HashMap<u32, usize> = HashMap::new();
+    for (f, t) in faces.iter().enumerate() {
+        for k in 0..3 {
+            let (a, b) = (t[k], t[(k + 1) % 3]);
+            edges.entry((a.min(b), a.max(b))).or_default().push((f, a < b));
+            let next = vertices.len();
+            vertices.entry(a).or_insert(next);
+        }
+    }
+
+    // Walk the faces across manifold edges, flipping a face when it runs the shared edge the
+    // same way as its neighbor. A face that would need both windings makes the mesh
+    // non-orientable.
+    let mut flipped: Vec<Option<bool>> = vec![None; faces.len()];
+    let mut components = 0;
+    let mut orientable = true;
+    let mut queue = VecDeque::new();
+    for start in 0..faces.len() {
+        if flipped[start].is_some() {
+            continue;
+        }
+        components += 1;
+        flipped[start] = Some(false);
+        queue.push_back(start);
+        while let Some(f) = queue.pop_front() {
+            let t = faces[f];
+            for k in 0..3 {
+                let (a, b) = (t[k], t[(k + 1) % 3]);
+                let around = &edges[&(a.min(b), a.max(b))];
+                if around.len() != 2 {
+                    continue;
+                }
+                let (forward, flip) = (a < b, flipped[f].unwrap());
+                for &(g, g_forward) in around {
+                    if g == f {
+                        continue;
+                    }
+                    let g_flip = flip ^ (g_forward == forward);
+                    match flipped[g] {
+                        None => {
+                            flipped[g] = Some(g_flip);
+                            queue.push_back(g);
+                        }
+                        Some(x) if x != g_flip => orientable = false,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    let boundary_edges = edges.values().filter(|e| e.len() == 1).count();
+    let non_manifold_edges = edges.values().filter(|e| e.len() > 2).count();
+    Topology {
+        vertices: vertices.len(),
+        edges: edges.len(),
+        faces: faces.len(),
+        boundary_edges,
+        non_manifold_edges,
+        components,
+        closed: boundary_edges == 0,
+        orientable,
+        euler_characteristic: vertices.len() as i64 - edges.len() as i64 + faces.len() as i64,
+    }
+}
+
+// Tolerance for welding positions that should coincide but went through different rounding,
+// as on the seams of parametric surfaces: 1e-5 of the largest extent of the bounding box.
+pub fn weld_tolerance(positions:&[[f32; 3]]) -> f32 {
+    let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+    for p in positions {
+        for k in 0..3 {
+            min[k] = min[k].min(p[k]);
+            max[k] = max[k].max(p[k]);
+        }
+    }
+    let size = (0..3).map(|k| (max[k] - min[k]).max(0.0)).fold(0.0f32, f32::max);
+    1e-5 * size
+}
+
+// For every vertex the index of the first vertex within tolerance of it, found on a hash grid
+// with cells of the tolerance.
+pub fn weld_by_position(positions:&[[f32; 3]], tolerance:f32) -> Vec<u32> {
+    // a zero tolerance welds exact matches only
+    let r = if tolerance > 0.0 { 1 } else { 0 };
+    let cell = |p:[f32; 3]| p.map(|c| if r > 0 { (c / tolerance).floor() as i64 } else { c.to_bits() as i64 });
+    let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+    let mut weld: Vec<u32> = vec![];
+    for (i, &p) in positions.iter().enumerate() {
+        let c = cell(p);
+        let mut found = None;
+        'search: for dx in -r..=r {
+            for dy in -r..=r {
+                for dz in -r..=r {
+                    for &j in grid.get(&[c[0] + dx, c[1] + dy, c[2] + dz]).into_iter().flatten() {
+                        let q = positions[j as usize];
+                        if (0..3).all(|k| (p[k] - q[k]).abs() <= tolerance) {
+                            found = Some(j);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+        let j = found.unwrap_or_else(|| {
+            grid.entry(c).or_default().push(i as u32);
+            i as u32
+        });
+        weld.push(j);
+    }
+    weld
+}
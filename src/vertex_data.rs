@@ -1,5 +1,8 @@
 #![allow(dead_code)]
 use cgmath::*;
+use std::collections::{HashMap, HashSet};
+use super::mesh::{Indices, Mesh};
+use anyhow::{bail, Result};
 
 #[derive(Default, Debug)]
 pub struct Vertex {
@@ -10,12 +13,35 @@ pub struct Vertex {
     pub bitang: [f32; 3],
 }
 
-pub fn create_tangent_data(
-    positions:&Vec<[f32;3]>, 
-    normals:&Vec<[f32;3]>, 
-    uvs:&Vec<[f32;2]>, 
-    indices:&Vec<u16>
+// Counts reported by create_tangent_data_with_stats.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TangentStats {
+    pub degenerate_triangles: usize, // triangles with collapsed uvs or zero area, skipped
+    pub fallback_vertices: usize,    // vertices whose frame was derived from the normal only
+}
+
+pub fn create_tangent_data<I: Copy + Into<u32>>(
+    positions:&[[f32;3]], 
+    normals:&[[f32;3]], 
+    uvs:&[[f32;2]], 
+    indices:&[I]
 ) -> Vec<Vertex> {
+    create_tangent_data_with_stats(positions, normals, uvs, indices).0
+}
+
+// Any unit vector perpendicular to n, used when a vertex has no usable uv derivatives.
+pub(crate) fn perpendicular(n:Vector3<f32>) -> Vector3<f32> {
+    let a = if n.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+    (a - n * a.dot(n)).normalize()
+}
+
+pub fn create_tangent_data_with_stats<I: Copy + Into<u32>>(
+    positions:&[[f32;3]], 
+    normals:&[[f32;3]], 
+    uvs:&[[f32;2]], 
+    indices:&[I]
+) -> (Vec<Vertex>, TangentStats) {
+    let mut stats = TangentStats::default();
     let mut vertices:Vec<Vertex> = vec![];
     for i in 0..positions.len() {
         vertices.push(Vertex { 
@@ -27,9 +53,8 @@ pub fn create_tangent_data(
         })
     }
 
-    let mut triangles:Vec<u16> = vec![0; vertices.len()];
     for i in (0..indices.len()).step_by(3) {
-        let c = [indices[i], indices[i+1], indices[i+2]];
+        let c: [u32; 3] = [indices[i].into(), indices[i+1].into(), indices[i+2].into()];
         let v0 = &vertices[c[0] as usize];
         let v1 = &vertices[c[1] as usize];
         let v2 = &vertices[c[2] as usize];
@@ -46,7 +71,17 @@ pub fn create_tangent_data(
         let duv1 = uv1 - uv0;
         let duv2 = uv2 - uv0;
 
-        let d = 1.0/(duv1[0]*duv2[1] - duv1[1]*duv2[0]);
+        // skip triangles whose uvs collapse to a line or a point (e.g. at sphere poles) and
+        // triangles without area; they have no well-defined tangent direction
+        let det = duv1[0]*duv2[1] - duv1[1]*duv2[0];
+        let area = dp1.cross(dp2).magnitude();
+        if !det.is_finite() || det.abs() <= 1.0e-6 * duv1.magnitude() * duv2.magnitude() 
+            || area.is_nan() || area <= 0.0 {
+            stats.degenerate_triangles += 1;
+            continue;
+        }
+
+        let d = 1.0/det;
         let tangent = [
             (dp1[0]*duv2[1] - dp2[0]*duv1[1])*d, 
             (dp1[1]*duv2[1] - dp2[1]*duv1[1])*d, 
@@ -58,28 +93,16 @@ pub fn create_tangent_data(
             (dp2[2]*duv1[0] - dp1[2]*duv2[0])*(-d),
         ];
         
-        vertices[c[0] as usize].tang =(Vector3::from(tangent) + Vector3::from(vertices[c[0] as usize].tang)).into();
-        vertices[c[1] as usize].tang =(Vector3::from(tangent) + Vector3::from(vertices[c[1] as usize].tang)).into();
-        vertices[c[2] as usize].tang =(Vector3::from(tangent) + Vector3::from(vertices[c[2] as usize].tang)).into();
-        vertices[c[0] as usize].bitang =(Vector3::from(bitangent) + Vector3::from(vertices[c[0] as usize].bitang)).into();
-        vertices[c[1] as usize].bitang =(Vector3::from(bitangent) + Vector3::from(vertices[c[1] as usize].bitang)).into();
-        vertices[c[2] as usize].bitang =(Vector3::from(bitangent) + Vector3::from(vertices[c[2] as usize].bitang)).into();
-
-        triangles[c[0] as usize] += 1;
-        triangles[c[1] as usize] += 1;
-        triangles[c[2] as usize] += 1;
-    }
-
-    // average tangents and bitangents
-    for i in 0..triangles.len() {
-        let n = triangles[i];
-        vertices[i].tang = (Vector3::from(vertices[i].tang)/n as f32).into();
-        vertices[i].bitang = (Vector3::from(vertices[i].bitang)/n as f32).into();
+        for k in c {
+            let v = &mut vertices[k as usize];
+            v.tang = (Vector3::from(tangent) + Vector3::from(v.tang)).into();
+            v.bitang = (Vector3::from(bitangent) + Vector3::from(v.bitang)).into();
+        }
     }
 
-    // Gram-Schmidt orthogonalization
-    for i in 0..vertices.len() {
-        let v = &mut vertices[i];
+    // Gram-Schmidt orthogonalization; the accumulated sums are normalized here, so there is
+    // no need to divide them by the number of triangles first
+    for v in vertices.iter_mut() {
         let n = Vector3::from(v.norm);
         let t = Vector3::from(v.tang);
         let b = Vector3::from(v.bitang);
@@ -87,22 +110,144 @@ pub fn create_tangent_data(
         // calculate t1
         let dot_tn = t.dot(n);
         let mut t1 = n * dot_tn;
-        t1 = (t - t1).normalize();
+        t1 = t - t1;
         
         // calculate b1
-        let dot_bn = b.dot(n);
-        let dot_bt = b.dot(t1);
-        let b_bn = n * dot_bn;
-        let b_bt = t1 * dot_bt;
-        let mut b1 = b - b_bn;
-        b1 = (b1 - b_bt).normalize();
+        let mut b1 = b - n * b.dot(n);
+
+        // fall back to a frame derived from the normal when the accumulated tangent is zero
+        // or parallel to the normal; keep the uv handedness if the bitangent is usable
+        let len = t1.magnitude();
+        if len.is_nan() || len <= 1.0e-12 {
+            stats.fallback_vertices += 1;
+            t1 = perpendicular(n);
+            let sign = if b1.dot(n.cross(t1)) < 0.0 { -1.0 } else { 1.0 };
+            b1 = n.cross(t1) * sign;
+        } else {
+            t1 = t1.normalize();
+            b1 = b1 - t1 * b1.dot(t1);
+            b1 = if b1.magnitude() > 1.0e-12 { b1.normalize() } else { n.cross(t1) };
+        }
 
         v.tang = t1.into();
         v.bitang = b1.into();
     }
-    vertices
+    (vertices, stats)
+}
+
+struct MikkGeometry<'a> {
+    positions: &'a [[f32; 3]],
+    normals: &'a [[f32; 3]],
+    uvs: &'a [[f32; 2]],
+    indices: &'a [u32],
+    corners: Vec<[f32; 4]>,  // one tangent per triangle corner
+}
+
+impl<'a> MikkGeometry<'a> {
+    fn index(&self, face: usize, vert: usize) -> usize {
+        self.indices[face * 3 + vert] as usize
+    }
 }
 
+impl<'a> bevy_mikktspace::Geometry for MikkGeometry<'a> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.positions[self.index(face, vert)]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.normals[self.index(face, vert)]
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.uvs[self.index(face, vert)]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.corners[face * 3 + vert] = tangent;
+    }
+}
+
+// MikkTSpace tangents of a triangle mesh. Vertices whose corners get different tangents are
+// split: the copies follow the original vertices, indices points the corners at them and
+// remap gives the original vertex of every vertex, for copying the other attributes.
+#[derive(Debug, Clone)]
+pub struct MikkTangents {
+    pub tangents: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+    pub remap: Vec<u32>,
+}
+
+// Tangents following the MikkTSpace reference implementation, as expected by normal maps
+// baked in external tools. The w component stores the bitangent sign, so the bitangent is
+// recovered in the shader as cross(normal, tangent.xyz) * tangent.w; this keeps lighting
+// correct on mirrored uvs. MikkTSpace computes a tangent per triangle corner; where the
+// corners of a vertex disagree, e.g. with uvs mirrored across an edge, the vertex is split
+// so that every corner keeps its own tangent.
+pub fn create_mikktspace_tangents(
+    positions:&[[f32;3]], 
+    normals:&[[f32;3]], 
+    uvs:&[[f32;2]], 
+    indices:&[u32]
+) -> Result<MikkTangents> {
+    if normals.len() != positions.len() || uvs.len() != positions.len() {
+        bail!("positions, normals and uvs must have the same length");
+    }
+    if !indices.len().is_multiple_of(3) {
+        bail!("triangle index count {} is not a multiple of 3", indices.len());
+    }
+    if let Some(&i) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+        bail!("index {} out of range for {} vertices", i, positions.len());
+    }
+
+    let mut geometry = MikkGeometry { 
+        positions, 
+        normals, 
+        uvs, 
+        indices, 
+        corners: vec![[1.0, 0.0, 0.0, 1.0]; indices.len()],
+    };
+    if !bevy_mikktspace::generate_tangents(&mut geometry) {
+        bail!("MikkTSpace tangent generation failed");
+    }
+
+    // vertices no triangle uses keep the default tangent
+    let n = positions.len();
+    let mut out = MikkTangents {
+        tangents: vec![[1.0, 0.0, 0.0, 1.0]; n],
+        indices: Vec::with_capacity(indices.len()),
+        remap: (0..n as u32).collect(),
+    };
+    let same = |a:[f32; 4], b:[f32; 4]| (0..4).all(|k| (a[k] - b[k]).abs() <= 1e-6);
+    let mut copies: Vec<Vec<u32>> = vec![vec![]; n];  // the vertices standing for each original
+    for (&i, &t) in indices.iter().zip(&geometry.corners) {
+        let found = copies[i as usize].iter().cloned().find(|&c| same(out.tangents[c as usize], t));
+        let v = match found {
+            Some(v) => v,
+            None => {
+                let v = if copies[i as usize].is_empty() {
+                    out.tangents[i as usize] = t;
+                    i
+                } else {
+                    out.tangents.push(t);
+                    out.remap.push(i);
+                    out.tangents.len() as u32 - 1
+                };
+                copies[i as usize].push(v);
+                v
+            }
+        };
+        out.indices.push(v);
+    }
+    Ok(out)
+}
 
 pub fn torus_position(r_torus:f32, r_tube:f32, u:Deg<f32>, v: Deg<f32>) -> [f32; 3] {
     let x = (r_torus + r_tube * v.cos()) * u.cos();
@@ -111,47 +256,58 @@ pub fn torus_position(r_torus:f32, r_tube:f32, u:Deg<f32>, v: Deg<f32>) -> [f32;
     [x, y, z]
 }
 
-pub fn create_torus_data(r_torus:f32, r_tube:f32, n_torus:u16, n_tube:u16) 
--> (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<u16>, Vec<u16>) {
+// Torus around the y axis. The first and last ring and tube rows are duplicated so that the
+// uv seam can wrap; uv_lens sets how often the texture repeats along the ring (u) and around
+// the tube (v). Tangents follow the ring direction, i.e. increasing u.
+pub fn create_torus_data(r_torus:f32, r_tube:f32, n_torus:u16, n_tube:u16, uv_lens:[f32; 2]) -> Mesh {
     let mut positions: Vec<[f32; 3]> = vec![];
     let mut normals: Vec<[f32; 3]> = vec![];
-    let eps = 0.01 * 360.0/n_tube as f32;
+    let mut uvs: Vec<[f32; 2]> = vec![];
+    let mut tangents: Vec<[f32; 4]> = vec![];
     
     for i in 0..=n_torus {
-        let du = i as f32 * 360.0/n_torus as f32;
+        let du = Deg(i as f32 * 360.0/n_torus as f32);
         for j in 0..=n_tube {
-            let dv = j as f32 * 360.0/n_tube as f32;
-            let pos = torus_position(r_torus, r_tube, Deg(du), Deg(dv));
+            let dv = Deg(j as f32 * 360.0/n_tube as f32);
+            let pos = torus_position(r_torus, r_tube, du, dv);
             positions.push(pos);
 
-            // calculate normals
-            let nu = Vector3::from(torus_position(r_torus, r_tube, Deg(du+eps), Deg(dv))) -
-                     Vector3::from(torus_position(r_torus, r_tube, Deg(du-eps), Deg(dv)));
-            let nv = Vector3::from(torus_position(r_torus, r_tube, Deg(du), Deg(dv+eps))) -
-                     Vector3::from(torus_position(r_torus, r_tube, Deg(du), Deg(dv-eps)));
-            let normal = nu.cross(nv).normalize();
-            normals.push(normal.into());
+            // normals and tangents from the analytic derivatives of torus_position; the 
+            // bitangent (along the tube) is n x t, so the handedness is always +1
+            normals.push([dv.cos() * du.cos(), dv.sin(), -dv.cos() * du.sin()]);
+            tangents.push([-du.sin(), 0.0, -du.cos(), 1.0]);
+
+            uvs.push([uv_lens[0] * i as f32/n_torus as f32, uv_lens[1] * j as f32/n_tube as f32]);
         }
     }
 
-    let mut indices: Vec<u16> = vec![];
-    let mut indices2: Vec<u16> = vec![];
-    let vertices_per_row = n_tube + 1;
+    let mut indices: Vec<u32> = vec![];
+    let mut indices2: Vec<u32> = vec![];
+    let vertices_per_row = n_tube as u32 + 1;
 
-    for i in 0..n_torus {
-        for j in 0..n_tube {
+    for i in 0..n_torus as u32 {
+        for j in 0..n_tube as u32 {
             let idx0 = j + i * vertices_per_row;
             let idx1 = j + 1 + i * vertices_per_row;
             let idx2 = j + 1 + (i + 1) * vertices_per_row;
             let idx3 = j + (i + 1) * vertices_per_row; 
-            let values:Vec<u16> = vec![idx0, idx1, idx2, idx2, idx3, idx0];
+            let values:Vec<u32> = vec![idx0, idx1, idx2, idx2, idx3, idx0];
             indices.extend(values);
-            let values2:Vec<u16> = vec![idx0, idx1, idx0, idx3];
+            let values2:Vec<u32> = vec![idx0, idx1, idx0, idx3];
             indices2.extend(values2);
         }
     }
 
-    (positions, normals, indices, indices2)
+    let n = positions.len();
+    Mesh {
+        positions,
+        normals: Some(normals),
+        uvs: Some(uvs),
+        tangents: Some(tangents),
+        indices: Indices::auto(indices, n),
+        indices2: Indices::auto(indices2, n),
+        ..Default::default()
+    }
 }
 
 
@@ -161,54 +317,127 @@ fn cylinder_position(r:f32, theta:Deg<f32>, y:f32) -> [f32; 3] {
     [x, y, z]
 }
 
-pub fn create_cylinder_data(mut rin:f32, rout:f32, h:f32, n:u16) -> (Vec<[f32; 3]>, Vec<u16>, Vec<u16>) {
-    if rin >= 0.999 * rout { 
-        rin = 0.999 * rout; 
-    }
+// Collects split vertices for generators that build a mesh from several parts.
+#[derive(Default)]
+pub(crate) struct MeshBuilder {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub tangents: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+    pub indices2: Vec<u32>,
+}
 
-    let mut positions: Vec<[f32; 3]> = vec![];
-    for i in 0..=n {
-        let theta = i as f32 * 360.0/n as f32;
-        let p0 = cylinder_position(rout, Deg(theta), h/2.0);
-        let p1 = cylinder_position(rout, Deg(theta), -h/2.0);
-        let p2 = cylinder_position(rin, Deg(theta), -h/2.0);
-        let p3 = cylinder_position(rin, Deg(theta), h/2.0);
-        let values:Vec<[f32; 3]> = vec![p0, p1, p2, p3];
-        positions.extend(values);
+impl MeshBuilder {
+    pub fn push(&mut self, pos:[f32; 3], normal:[f32; 3], uv:[f32; 2], tangent:[f32; 4]) -> u32 {
+        self.positions.push(pos);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+        self.tangents.push(tangent);
+        self.positions.len() as u32 - 1
     }
 
-    let mut indices: Vec<u16> = vec![];
-    let mut indices2: Vec<u16> = vec![];
+    pub fn build(self) -> Mesh {
+        let n = self.positions.len();
+        Mesh {
+            positions: self.positions,
+            normals: Some(self.normals),
+            uvs: Some(self.uvs),
+            colors: None,
+            tangents: Some(self.tangents),
+            indices: Indices::auto(self.indices, n),
+            indices2: Indices::auto(self.indices2, n),
+        }
+    }
+}
 
-    for i in 0..n {
-        let idx0 = i*4;
-        let idx1 = i*4 + 1;
-        let idx2 = i*4 + 2;
-        let idx3 = i*4 + 3;
-        let idx4 = i*4 + 4;
-        let idx5 = i*4 + 5;
-        let idx6 = i*4 + 6;
-        let idx7 = i*4 + 7;
-
-        // triangle indices
-        let values: Vec<u16> = vec![
-            idx0, idx4, idx7, idx7, idx3, idx0, // top
-            idx1, idx2, idx6, idx6, idx5, idx1, // bottom
-            idx0, idx1, idx5, idx5, idx4, idx0, // outer
-            idx2, idx3, idx7, idx7, idx6, idx2  // inner
-        ];
-        indices.extend(values);
+// Cylinder along the y axis; with rin > 0 it becomes a tube with an inner wall and ring
+// shaped caps. The walls have smooth normals and cylindrical uvs (u around the axis, v from
+// top to bottom); the caps get their own vertices with flat normals and planar uvs.
+pub fn create_cylinder_data(mut rin:f32, rout:f32, h:f32, n:u16) -> Mesh {
+    if rin >= 0.999 * rout { 
+        rin = 0.999 * rout; 
+    }
+    let rin = rin.max(0.0);
+    let n = n as u32;
+    let mut b = MeshBuilder::default();
+
+    // outer wall, then the inner wall with inward normals
+    let mut walls = vec![(rout, 1.0)];
+    if rin > 0.0 {
+        walls.push((rin, -1.0));
+    }
+    for (r, s) in walls {
+        let base = b.positions.len() as u32;
+        for i in 0..=n {
+            let theta = Deg(i as f32 * 360.0/n as f32);
+            for (y, v) in [(h/2.0, 0.0), (-h/2.0, 1.0)] {
+                b.push(
+                    cylinder_position(r, theta, y), 
+                    [s * theta.cos(), 0.0, -s * theta.sin()], 
+                    [i as f32/n as f32, v], 
+                    [-theta.sin(), 0.0, -theta.cos(), -s],
+                );
+            }
+        }
+        for i in 0..n {
+            let (top0, bottom0) = (base + 2*i, base + 2*i + 1);
+            let (top1, bottom1) = (top0 + 2, bottom0 + 2);
+            if s > 0.0 {
+                b.indices.extend([top0, bottom0, bottom1, bottom1, top1, top0]);
+            } else {
+                b.indices.extend([bottom0, top0, top1, top1, bottom1, bottom0]);
+            }
+            b.indices2.extend([top0, bottom0]);
+        }
+    }
 
-        // wireframe indices
-        let values2: Vec<u16> = vec![
-            idx0, idx3, idx3, idx7, idx4, idx0, // top
-            idx1, idx2, idx2, idx6, idx5, idx1, // bottom
-            idx0, idx1, idx3, idx2              // side
-        ];
-        indices2.extend(values2);
+    // caps: planar uvs from the top view, mirrored in z for the bottom cap so the texture 
+    // is not flipped when seen from below
+    for (y, ny) in [(h/2.0, 1.0), (-h/2.0, -1.0)] {
+        let uv = |p:[f32; 3]| [0.5 + 0.5 * p[0]/rout, 0.5 + 0.5 * ny * p[2]/rout];
+        let normal = [0.0, ny, 0.0];
+        let tangent = [1.0, 0.0, 0.0, -1.0];
+        if rin > 0.0 {
+            let base = b.positions.len() as u32;
+            for i in 0..=n {
+                let theta = Deg(i as f32 * 360.0/n as f32);
+                for r in [rout, rin] {
+                    let p = cylinder_position(r, theta, y);
+                    b.push(p, normal, uv(p), tangent);
+                }
+            }
+            for i in 0..n {
+                let (out0, in0) = (base + 2*i, base + 2*i + 1);
+                let (out1, in1) = (out0 + 2, in0 + 2);
+                if ny > 0.0 {
+                    b.indices.extend([out0, out1, in1, in1, in0, out0]);
+                } else {
+                    b.indices.extend([out0, in0, in1, in1, out1, out0]);
+                }
+                b.indices2.extend([out0, out1, in0, in1, out0, in0]);
+            }
+        } else {
+            // solid cylinder: a triangle fan around the cap center
+            let p = [0.0, y, 0.0];
+            let center = b.push(p, normal, uv(p), tangent);
+            for i in 0..=n {
+                let p = cylinder_position(rout, Deg(i as f32 * 360.0/n as f32), y);
+                b.push(p, normal, uv(p), tangent);
+            }
+            for i in 0..n {
+                let (out0, out1) = (center + 1 + i, center + 2 + i);
+                if ny > 0.0 {
+                    b.indices.extend([out0, out1, center]);
+                } else {
+                    b.indices.extend([out0, center, out1]);
+                }
+                b.indices2.extend([out0, out1, out0, center]);
+            }
+        }
     }
 
-    (positions, indices, indices2)
+    b.build()
 }
 
 fn sphere_position(r:f32, theta:Deg<f32>, phi:Deg<f32>) -> [f32; 3] {
@@ -218,8 +447,7 @@ fn sphere_position(r:f32, theta:Deg<f32>, phi:Deg<f32>) -> [f32; 3] {
     [x, y, z]
 }
 
-pub fn create_sphere_data(r:f32, u:u16, v:u16) -> 
-(Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<u16>, Vec<u16>) {
+pub fn create_sphere_data(r:f32, u:u16, v:u16) -> Mesh {
     let mut positions: Vec<[f32; 3]> = vec![];
     let mut normals: Vec<[f32; 3]> = vec![];
     let mut uvs: Vec<[f32; 2]> = vec![];
@@ -235,29 +463,36 @@ pub fn create_sphere_data(r:f32, u:u16, v:u16) ->
         }
     }
 
-    let mut indices: Vec<u16> = vec![];
-    let mut indices2: Vec<u16> = vec![];
+    let mut indices: Vec<u32> = vec![];
+    let mut indices2: Vec<u32> = vec![];
     
-    for i in 0..u {
-        for j in 0..v {
-            let idx0 = j + i * (v as u16 + 1);
-            let idx1 = j + 1 + i * (v as u16 + 1);
-            let idx2 = j + 1 + (i + 1) * (v as u16 + 1);
-            let idx3 = j + (i + 1) * (v as u16 + 1);
-
-            let values: Vec<u16> = vec![idx0, idx1, idx2, idx2, idx3, idx0];
+    for i in 0..u as u32 {
+        for j in 0..v as u32 {
+            let idx0 = j + i * (v as u32 + 1);
+            let idx1 = j + 1 + i * (v as u32 + 1);
+            let idx2 = j + 1 + (i + 1) * (v as u32 + 1);
+            let idx3 = j + (i + 1) * (v as u32 + 1);
+
+            let values: Vec<u32> = vec![idx0, idx1, idx2, idx2, idx3, idx0];
             indices.extend(values); 
            
-            let values2: Vec<u16> = vec![idx0, idx1, idx0, idx3];
+            let values2: Vec<u32> = vec![idx0, idx1, idx0, idx3];
             indices2.extend(values2); 
         }
     }
 
-    (positions, normals, uvs, indices, indices2)
+    let n = positions.len();
+    Mesh {
+        positions,
+        normals: Some(normals),
+        uvs: Some(uvs),
+        indices: Indices::auto(indices, n),
+        indices2: Indices::auto(indices2, n),
+        ..Default::default()
+    }
 }
 
-pub fn create_cube_data(side:f32) -> (Vec<[f32; 3]>, Vec<[f32; 3]>,Vec<[f32; 3]>,
-    Vec<[f32; 2]>,Vec<u16>,Vec<u16>) {
+pub fn create_cube_data(side:f32) -> Mesh {
         let s2 = side / 2.0;
         let positions = [
             [s2,  s2,  s2],     // index 0
@@ -310,7 +545,7 @@ pub fn create_cube_data(side:f32) -> (Vec<[f32; 3]>, Vec<[f32; 3]>,Vec<[f32; 3]>
             [0., 1.], [1., 1.], [0., 0.], [1., 0.], [0., 1.], [1., 1.], [0., 0.], [1., 0.], 
         ];
     
-        let indices = [
+        let indices: [u16; 36] = [
             0,  2,  1, 2,  3,  1,
             4,  6,  5, 6,  7,  5,
             8, 10,  9, 10, 11, 9,
@@ -319,13 +554,20 @@ pub fn create_cube_data(side:f32) -> (Vec<[f32; 3]>, Vec<[f32; 3]>,Vec<[f32; 3]>
             20, 22, 21, 22, 23, 21,
         ];
     
-        let indices2 = [
+        let indices2: [u16; 24] = [
             8, 9, 9, 11, 11, 10, 10, 8,     // top
             14, 15, 15, 13, 13, 12, 12, 14, // bottom
             11, 13, 9, 15, 8, 14, 10, 12,   // side
         ];
-    (positions.to_vec(), colors.to_vec(), normals.to_vec(), uvs.to_vec(), 
-     indices.to_vec(), indices2.to_vec())
+    Mesh {
+        positions: positions.to_vec(),
+        normals: Some(normals.to_vec()),
+        uvs: Some(uvs.to_vec()),
+        colors: Some(colors.to_vec()),
+        tangents: None,
+        indices: Indices::U16(indices.to_vec()),
+        indices2: Indices::U16(indices2.to_vec()),
+    }
 }
     
 pub fn create_cube_uv() -> Vec<[f32; 2]> {
@@ -337,4 +579,409 @@ pub fn create_cube_uv() -> Vec<[f32; 2]> {
         [0., 1.], [1./3., 1.], [0., 1./2.], [1./3., 1./2.],           // front
         [2./3., 1.], [1., 1.], [2./3., 1./2.], [1., 1./2.]            // back
     ].to_vec()
-}
\ No newline at end of file
+}
+// Subdivided plane in the xz plane facing +y, centered at the origin. uv_lens scales the uvs
+// to tile the texture, as in create_torus_data.
+pub fn create_plane_data(width:f32, depth:f32, nx:u16, nz:u16, uv_lens:[f32; 2]) -> Mesh {
+    let (nx, nz) = (nx.max(1) as u32, nz.max(1) as u32);
+    let mut b = MeshBuilder::default();
+    for j in 0..=nz {
+        for i in 0..=nx {
+            let (s, t) = (i as f32/nx as f32, j as f32/nz as f32);
+            b.push(
+                [width * (s - 0.5), 0.0, depth * (t - 0.5)], 
+                [0.0, 1.0, 0.0], 
+                [uv_lens[0] * s, uv_lens[1] * t], 
+                [1.0, 0.0, 0.0, -1.0],
+            );
+        }
+    }
+    for j in 0..nz {
+        for i in 0..nx {
+            let idx0 = i + j * (nx + 1);
+            let idx1 = idx0 + 1;
+            let idx2 = idx1 + nx + 1;
+            let idx3 = idx0 + nx + 1;
+            b.indices.extend([idx0, idx3, idx2, idx2, idx1, idx0]);
+            b.indices2.extend([idx0, idx1, idx0, idx3]);
+        }
+    }
+    for i in 0..nx {
+        let idx = i + nz * (nx + 1);
+        b.indices2.extend([idx, idx + 1]);
+    }
+    for j in 0..nz {
+        let idx = nx + j * (nx + 1);
+        b.indices2.extend([idx, idx + nx + 1]);
+    }
+    b.build()
+}
+
+// Equirectangular uv of a unit direction, using the same angles as sphere_position: u follows
+// the longitude phi and v runs from the north pole (0) to the south pole (1).
+fn sphere_uv(d:Vector3<f32>) -> [f32; 2] {
+    let phi = (-d.z).atan2(d.x);
+    let u = if phi < 0.0 { phi / (2.0 * std::f32::consts::PI) + 1.0 } else { phi / (2.0 * std::f32::consts::PI) };
+    let v = d.y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+    [u, v]
+}
+
+// Tangent along increasing longitude; the bitangent points south, which gives w = -1.
+fn sphere_tangent(u:f32) -> [f32; 4] {
+    let phi = Rad(u * 2.0 * std::f32::consts::PI);
+    [-phi.sin(), 0.0, -phi.cos(), -1.0]
+}
+
+// Sphere built by subdividing an icosahedron, which spreads the triangles evenly instead of
+// crowding them at the poles like create_sphere_data. Vertices on the uv seam and at the
+// poles are duplicated so equirectangular textures such as assets/earth.png wrap without
+// a smeared column.
+pub fn create_icosphere_data(r:f32, subdivisions:u32) -> Mesh {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut dirs: Vec<Vector3<f32>> = [
+        [-1.0, t, 0.0], [1.0, t, 0.0], [-1.0, -t, 0.0], [1.0, -t, 0.0],
+        [0.0, -1.0, t], [0.0, 1.0, t], [0.0, -1.0, -t], [0.0, 1.0, -t],
+        [t, 0.0, -1.0], [t, 0.0, 1.0], [-t, 0.0, -1.0], [-t, 0.0, 1.0],
+    ].iter().map(|p| Vector3::from(*p).normalize()).collect();
+    let mut faces: Vec<[usize; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a:usize, b:usize, dirs:&mut Vec<Vector3<f32>>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                dirs.push((dirs[a] + dirs[b]).normalize());
+                dirs.len() - 1
+            })
+        };
+        let mut next = Vec::with_capacity(faces.len() * 4);
+        for [a, b, c] in faces {
+            let ab = midpoint(a, b, &mut dirs);
+            let bc = midpoint(b, c, &mut dirs);
+            let ca = midpoint(c, a, &mut dirs);
+            next.extend([[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]);
+        }
+        faces = next;
+    }
+
+    // split vertices by uv: triangles crossing the seam get u + 1 on their low side, and the
+    // pole vertices take the mean u of the other two corners
+    let mut b = MeshBuilder::default();
+    let mut ids: HashMap<(usize, u32), u32> = HashMap::new();
+    let mut lines: HashSet<(u32, u32)> = HashSet::new();
+    for face in faces {
+        let mut uvs = face.map(|i| sphere_uv(dirs[i]));
+        let us = uvs.map(|uv| uv[0]);
+        if us.iter().cloned().fold(f32::MIN, f32::max) - us.iter().cloned().fold(f32::MAX, f32::min) > 0.5 {
+            for uv in uvs.iter_mut() {
+                if uv[0] < 0.5 {
+                    uv[0] += 1.0;
+                }
+            }
+        }
+        let pole = |i:usize| dirs[face[i]].y.abs() > 1.0 - 1e-6;
+        for k in 0..3 {
+            if pole(k) {
+                uvs[k][0] = 0.5 * (uvs[(k + 1) % 3][0] + uvs[(k + 2) % 3][0]);
+            }
+        }
+        let tri: Vec<u32> = (0..3).map(|k| {
+            let d = dirs[face[k]];
+            *ids.entry((face[k], uvs[k][0].to_bits())).or_insert_with(|| {
+                b.push((d * r).into(), d.into(), uvs[k], sphere_tangent(uvs[k][0]))
+            })
+        }).collect();
+        b.indices.extend(&tri);
+        for k in 0..3 {
+            let (p, q) = (face[k], face[(k + 1) % 3]);
+            if lines.insert((p.min(q) as u32, p.max(q) as u32)) {
+                b.indices2.extend([tri[k], tri[(k + 1) % 3]]);
+            }
+        }
+    }
+    b.build()
+}
+
+// Maps a point of the cube [-1, 1]^3 onto the unit sphere with less area distortion than
+// plain normalization.
+fn spherify(p:Vector3<f32>) -> Vector3<f32> {
+    let (x2, y2, z2) = (p.x * p.x, p.y * p.y, p.z * p.z);
+    Vector3::new(
+        p.x * (1.0 - y2/2.0 - z2/2.0 + y2 * z2/3.0).sqrt(),
+        p.y * (1.0 - z2/2.0 - x2/2.0 + z2 * x2/3.0).sqrt(),
+        p.z * (1.0 - x2/2.0 - y2/2.0 + x2 * y2/3.0).sqrt(),
+    ).normalize()
+}
+
+// Sphere made from a subdivided cube with n x n quads per face. Every face has its own 0..1
+// uvs (u to the right and v down when looking at the face from outside), so a texture is
+// repeated on the six faces without pole distortion.
+pub fn create_cube_sphere_data(r:f32, n:u16) -> Mesh {
+    let n = n.max(1) as u32;
+    // face normal, u axis and v axis
+    let faces: [[[f32; 3]; 3]; 6] = [
+        [[1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]],   // right
+        [[-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]],   // left
+        [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],     // top
+        [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]],   // bottom
+        [[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]],    // front
+        [[0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]],  // back
+    ];
+
+    let mut b = MeshBuilder::default();
+    for [fn_, fu, fv] in faces {
+        let (axis_n, axis_u, axis_v) = (Vector3::from(fn_), Vector3::from(fu), Vector3::from(fv));
+        let base = b.positions.len() as u32;
+        for j in 0..=n {
+            for i in 0..=n {
+                let (s, t) = (i as f32/n as f32, j as f32/n as f32);
+                let d = spherify(axis_n + axis_u * (2.0 * s - 1.0) + axis_v * (2.0 * t - 1.0));
+                let tangent = (axis_u - d * d.dot(axis_u)).normalize();
+                let w = if d.cross(tangent).dot(axis_v) < 0.0 { -1.0 } else { 1.0 };
+                b.push((d * r).into(), d.into(), [s, t], [tangent.x, tangent.y, tangent.z, w]);
+            }
+        }
+        for j in 0..n {
+            for i in 0..n {
+                let idx0 = base + i + j * (n + 1);
+                let idx1 = idx0 + 1;
+                let idx2 = idx1 + n + 1;
+                let idx3 = idx0 + n + 1;
+                b.indices.extend([idx0, idx3, idx2, idx2, idx1, idx0]);
+                b.indices2.extend([idx0, idx1, idx0, idx3]);
+            }
+        }
+    }
+    b.build()
+}
+
+// One ring of a surface of revolution around the y axis: radius, height, the (radial, y)
+// components of the normal and the v coordinate.
+pub(crate) struct LatheRing {
+    pub rho: f32,
+    pub y: f32,
+    pub normal: [f32; 2],
+    pub v: f32,
+}
+
+// Sweeps the rings around the y axis with n segments, u = i/n following the angle like the
+// cylinder walls. Rings must run from top to bottom. A ring with zero radius is a pole: it gets
+// one vertex per segment with u at the segment center, and the collapsed triangles next to it
+// are left out.
+pub(crate) fn lathe(b:&mut MeshBuilder, rings:&[LatheRing], n:u32) {
+    let mut starts: Vec<u32> = vec![];
+    for ring in rings {
+        starts.push(b.positions.len() as u32);
+        let pole = ring.rho <= 0.0;
+        let count = if pole { n } else { n + 1 };
+        for i in 0..count {
+            let s = if pole { i as f32 + 0.5 } else { i as f32 };
+            let theta = Deg(s * 360.0/n as f32);
+            b.push(
+                cylinder_position(ring.rho.max(0.0), theta, ring.y), 
+                [ring.normal[0] * theta.cos(), ring.normal[1], -ring.normal[0] * theta.sin()], 
+                [s/n as f32, ring.v], 
+                [-theta.sin(), 0.0, -theta.cos(), -1.0],
+            );
+        }
+    }
+    for k in 0..rings.len() - 1 {
+        let (top, bottom) = (rings[k].rho > 0.0, rings[k + 1].rho > 0.0);
+        for i in 0..n {
+            let idx0 = starts[k] + i;
+            let idx3 = starts[k + 1] + i;
+            if top && bottom {
+                let (idx1, idx2) = (idx0 + 1, idx3 + 1);
+                b.indices.extend([idx0, idx3, idx2, idx2, idx1, idx0]);
+            } else if bottom {
+                b.indices.extend([idx0, idx3, idx3 + 1]);
+            } else if top {
+                b.indices.extend([idx3, idx0 + 1, idx0]);
+            }
+            if top {
+                b.indices2.extend([idx0, idx0 + 1]);
+            }
+            b.indices2.extend([idx0, idx3]);
+        }
+    }
+}
+
+// Cone along the y axis with the apex at h/2 and a flat base at -h/2. The side has smooth
+// normals and uvs running from the apex (v = 0) to the base (v = 1); the base is a separate
+// disk with planar uvs like the caps of create_cylinder_data.
+pub fn create_cone_data(r:f32, h:f32, n:u16) -> Mesh {
+    let n = n.max(3) as u32;
+    let slant = (r * r + h * h).sqrt();
+    let normal = [h/slant, r/slant];
+    let mut b = MeshBuilder::default();
+    lathe(&mut b, &[
+        LatheRing { rho: 0.0, y: h/2.0, normal, v: 0.0 },
+        LatheRing { rho: r, y: -h/2.0, normal, v: 1.0 },
+    ], n);
+
+    let p = [0.0, -h/2.0, 0.0];
+    let uv = |p:[f32; 3]| [0.5 + 0.5 * p[0]/r, 0.5 - 0.5 * p[2]/r];
+    let center = b.push(p, [0.0, -1.0, 0.0], uv(p), [1.0, 0.0, 0.0, -1.0]);
+    for i in 0..=n {
+        let p = cylinder_position(r, Deg(i as f32 * 360.0/n as f32), -h/2.0);
+        b.push(p, [0.0, -1.0, 0.0], uv(p), [1.0, 0.0, 0.0, -1.0]);
+    }
+    for i in 0..n {
+        b.indices.extend([center + 1 + i, center, center + 2 + i]);
+        b.indices2.extend([center + 1 + i, center + 2 + i]);
+    }
+    b.build()
+}
+
+// Capsule along the y axis: a cylinder of radius r and height h closed by two hemispheres
+// with n_cap rings each. v is proportional to the arc length along the profile, so the
+// texture is not stretched between the body and the caps.
+pub fn create_capsule_data(r:f32, h:f32, n:u16, n_cap:u16) -> Mesh {
+    let (n, n_cap) = (n.max(3) as u32, n_cap.max(1) as u32);
+    let length = std::f32::consts::PI * r + h;
+    let mut rings: Vec<LatheRing> = vec![];
+    for (y0, k0) in [(h/2.0, 0), (-h/2.0, n_cap)] {
+        for k in k0..=k0 + n_cap {
+            let theta = Deg(k as f32 * 90.0/n_cap as f32);
+            let arc = r * Rad::from(theta).0 + if k0 > 0 { h } else { 0.0 };
+            // the first and last rings are the poles; sin(180°) is not exactly 0 in f32, so
+            // lathe would otherwise see a tiny (possibly negative) ring there
+            let sin = if k == 0 || k == 2 * n_cap { 0.0 } else { theta.sin() };
+            rings.push(LatheRing { 
+                rho: r * sin, 
+                y: y0 + r * theta.cos(), 
+                normal: [sin, theta.cos()], 
+                v: arc/length,
+            });
+        }
+    }
+    let mut b = MeshBuilder::default();
+    lathe(&mut b, &rings, n);
+    b.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // both poles are single fans of triangles, whatever n_cap is
+    #[test]
+    fn capsule_poles() {
+        for n_cap in [1, 2, 5] {
+            let mesh = create_capsule_data(1.0, 2.0, 8, n_cap);
+            mesh.validate().unwrap();
+            let rings = 2 * n_cap as usize + 2;
+            // two fans of 8 triangles plus 2*8 triangles per band between the other rings
+            assert_eq!(mesh.triangle_count(), 2 * 8 + 2 * 8 * (rings - 3));
+            for (i, p) in mesh.positions.iter().enumerate() {
+                let rho = (p[0] * p[0] + p[2] * p[2]).sqrt();
+                if (p[1].abs() - 2.0).abs() < 1e-6 {
+                    assert_eq!(rho, 0.0, "pole vertex {} off the axis", i);
+                }
+            }
+        }
+    }
+
+    type Geometry = (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<u32>);
+
+    // A unit quad in the xy plane facing +z, split like the grids of this module.
+    fn quad(uvs:[[f32; 2]; 4]) -> Geometry {
+        let positions = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        (positions, vec![[0.0, 0.0, 1.0]; 4], uvs.to_vec(), vec![0, 1, 2, 2, 3, 0])
+    }
+
+    fn assert_tangent(t:[f32; 4], expected:[f32; 4]) {
+        for k in 0..4 {
+            assert!((t[k] - expected[k]).abs() < 1e-5, "tangent {:?}, expected {:?}", t, expected);
+        }
+    }
+
+    // The per-corner output of MikkTSpace itself, to check the split vertices against.
+    struct Corners<'a> {
+        positions: &'a [[f32; 3]],
+        normals: &'a [[f32; 3]],
+        uvs: &'a [[f32; 2]],
+        indices: &'a [u32],
+        tangents: Vec<[f32; 4]>,
+    }
+
+    impl<'a> bevy_mikktspace::Geometry for Corners<'a> {
+        fn num_faces(&self) -> usize { self.indices.len() / 3 }
+        fn num_vertices_of_face(&self, _face: usize) -> usize { 3 }
+        fn position(&self, f: usize, v: usize) -> [f32; 3] { self.positions[self.indices[f * 3 + v] as usize] }
+        fn normal(&self, f: usize, v: usize) -> [f32; 3] { self.normals[self.indices[f * 3 + v] as usize] }
+        fn tex_coord(&self, f: usize, v: usize) -> [f32; 2] { self.uvs[self.indices[f * 3 + v] as usize] }
+        fn set_tangent_encoded(&mut self, t: [f32; 4], f: usize, v: usize) { self.tangents[f * 3 + v] = t; }
+    }
+
+    fn reference(positions:&[[f32; 3]], normals:&[[f32; 3]], uvs:&[[f32; 2]], indices:&[u32]) -> Vec<[f32; 4]> {
+        let mut c = Corners { positions, normals, uvs, indices, tangents: vec![[0.0; 4]; indices.len()] };
+        assert!(bevy_mikktspace::generate_tangents(&mut c));
+        c.tangents
+    }
+
+    // every corner gets the MikkTSpace tangent of that corner, on a copy of its own vertex
+    fn assert_matches_reference(positions:&[[f32; 3]], normals:&[[f32; 3]], uvs:&[[f32; 2]], indices:&[u32]) -> MikkTangents {
+        let m = create_mikktspace_tangents(positions, normals, uvs, indices).unwrap();
+        let expected = reference(positions, normals, uvs, indices);
+        assert_eq!(m.indices.len(), indices.len());
+        assert_eq!(m.remap.len(), m.tangents.len());
+        for (c, (&i, &v)) in indices.iter().zip(&m.indices).enumerate() {
+            assert_eq!(m.remap[v as usize], i);
+            assert_tangent(m.tangents[v as usize], expected[c]);
+        }
+        m
+    }
+
+    // Reference values: with uvs equal to the positions MikkTSpace yields the tangent +x with
+    // w = 1; mirroring u flips the tangent to -x, and the bitangent cross(n, t) * w must still
+    // point along +v, i.e. +y, so w = -1.
+    #[test]
+    fn mikktspace_quad() {
+        let (p, n, uv, ind) = quad([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+        let m = assert_matches_reference(&p, &n, &uv, &ind);
+        assert_eq!(m.tangents.len(), 4);
+        for t in m.tangents {
+            assert_tangent(t, [1.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn mikktspace_mirrored_quad() {
+        let (p, n, uv, ind) = quad([[1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+        let m = assert_matches_reference(&p, &n, &uv, &ind);
+        assert_eq!(m.tangents.len(), 4);
+        for t in m.tangents {
+            assert_tangent(t, [-1.0, 0.0, 0.0, -1.0]);
+        }
+    }
+
+    // Two quads whose uvs mirror across their shared edge: MikkTSpace gives the corners at
+    // vertices 1 and 2 different tangents, so both are split.
+    #[test]
+    fn mikktspace_disagreeing_corners() {
+        let positions = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0],
+            [2.0, 0.0, 0.0], [2.0, 1.0, 0.0]];
+        let normals = vec![[0.0, 0.0, 1.0]; 6];
+        let uvs = vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0], [0.0, 1.0]];
+        for indices in [vec![0, 1, 2, 2, 3, 0, 1, 4, 5, 5, 2, 1], vec![1, 4, 5, 5, 2, 1, 0, 1, 2, 2, 3, 0]] {
+            let m = assert_matches_reference(&positions, &normals, &uvs, &indices);
+            let mut split = m.remap[6..].to_vec();
+            split.sort();
+            assert_eq!(split, [1, 2]);
+        }
+    }
+
+    // smooth meshes with continuous uvs need no splits
+    #[test]
+    fn mikktspace_sphere() {
+        let mesh = create_sphere_data(1.0, 12, 10);
+        let m = assert_matches_reference(&mesh.positions, mesh.normals.as_ref().unwrap(), 
+            mesh.uvs.as_ref().unwrap(), &mesh.indices.to_u32());
+        assert!(m.tangents.len() <= mesh.vertex_count() + 2 * 12);
+    }
+}